@@ -1,415 +1,1464 @@
 use anyhow::Result;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use crate::{Output, tools};
-use std::pin::Pin;
+use serde_json::Value;
+use crate::{ApiError, AskResult, GrepMatch, Output, TodoItem, TodoStatus, ToolDefinition, ToolError, tools};
+use crate::audit::{AuditDecision, AuditEntry, AuditLog, AuditStatus};
+use crate::builder::AgentBuilder;
+use crate::tools::{OutputStream, Tool};
+use crate::session::{self, Session};
+use crate::cache::HttpCache;
+use crate::config;
+use crate::egress::EgressPolicy;
+use crate::events::{AgentEvent, CompletedTool, EventSink};
+use crate::hooks::{HookEvent, HookOutcome, Hooks};
+use crate::instructions::Instructions;
+use crate::interrupt::Interrupt;
+use crate::permission::{self, Decision, PermissionMode};
+use crate::provider::{self, Provider};
+use crate::redact::{LineRedactor, Redactor};
+use crate::sandbox::Sandbox;
+use crate::shell::{self, Classification};
+use crate::usage::{Budget, BudgetStatus, PriceTable, Usage};
+use crate::workspace::{OutsideAccess, Workspace};
+use crate::types::parse_retry_after;
+use futures::stream::{self, StreamExt};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::task;
 
+/// A tool invocation requested by the model.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolCall {
+    pub id: String,
     pub tool: String,
-    pub args: Vec<String>,
-    pub description: String,
+    pub input: Value,
 }
 
+impl ToolCall {
+    /// One-line description of the call for progress output.
+    pub fn summary(&self) -> String {
+        let detail = ["path", "command", "pattern", "url", "query", "question"]
+            .iter()
+            .find_map(|key| self.input.get(key).and_then(Value::as_str))
+            .map(str::to_string)
+            .unwrap_or_else(|| self.input.to_string());
+        format!("{} {}", self.tool, detail)
+    }
+}
+
+/// What happened while a single tool call ran, for the audit log.
+#[derive(Debug, Default)]
+struct CallTrace {
+    approved: bool,
+    exit_code: Option<i32>,
+    /// The arguments a `pre_tool_use` hook replaced the model's with.
+    input: Option<Value>,
+}
+
+/// Why `Agent::process` returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// The model answered without requesting more tools.
+    Completed,
+    /// The model's reply was cut off at the output token limit.
+    MaxTokens,
+    /// The turn budget ran out while the model still wanted tools.
+    MaxTurns,
+    /// The tool-call budget ran out.
+    MaxToolCalls,
+    /// The user pressed Ctrl-C.
+    Interrupted,
+    /// A token or spending limit was reached.
+    BudgetExceeded,
+}
+
+/// What one `run` did.
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentResult {
+    pub stop_reason: StopReason,
+    /// Text of the model's last reply.
+    pub text: String,
+    /// Every tool call that ran, in order.
+    pub tools: Vec<CompletedTool>,
+    pub usage: Usage,
+    /// USD, if the model has a known price.
+    pub cost_usd: Option<f64>,
+}
+
+/// Decides tool calls that the permission mode says need approval.
+pub type Approver = Arc<dyn Fn(&ToolCall) -> bool + Send + Sync>;
+
+/// Recorded in the history when the user cancels a turn, so the model knows
+/// its last request or tool calls didn't finish.
+const INTERRUPTED_MARKER: &str = "[Request interrupted by user]";
+
+/// Built-in tools whose `path` argument is confined to the workspace.
+const FILE_TOOLS: &[&str] = &["read", "write", "edit", "glob", "grep"];
+
+/// How `call_api` retries rate limits, overloads and server errors.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    #[serde(rename = "base_delay_ms", with = "config::millis")]
+    pub base_delay: Duration,
+    #[serde(rename = "max_delay_ms", with = "config::millis")]
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `attempt` (starting at 1). A `retry-after`
+    /// from the server wins; otherwise exponential backoff, jittered
+    /// between half and all of the backoff.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(delay) = retry_after {
+            return delay.min(self.max_delay);
+        }
+        let ceiling = self
+            .base_delay
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(self.max_delay);
+        ceiling.mul_f64(0.5 + fastrand::f64() * 0.5)
+    }
+}
+
+/// Budgets for a single `process` call.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgentLimits {
+    /// Model round-trips per user request.
+    pub max_turns: u32,
+    /// Tool executions per user request.
+    pub max_tool_calls: u32,
+    /// Read-only tool calls allowed to run at the same time.
+    pub max_parallel_tools: usize,
+}
+
+impl Default for AgentLimits {
+    fn default() -> Self {
+        Self {
+            max_turns: 25,
+            max_tool_calls: 100,
+            max_parallel_tools: 8,
+        }
+    }
+}
+
+/// Extended thinking settings, from the `[thinking]` config table. Only
+/// sent to models that support thinking; see `supports_thinking`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThinkingSettings {
+    pub enabled: bool,
+    /// Tokens the model may spend thinking per turn (minimum 1024).
+    pub budget_tokens: u32,
+    /// Whether the terminal prints thinking dimmed or collapses it to a
+    /// single line. Only the renderer reads this.
+    pub show: bool,
+}
+
+impl Default for ThinkingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            budget_tokens: 4096,
+            show: true,
+        }
+    }
+}
+
+/// Models from before extended thinking, which reject requests carrying a
+/// `thinking` field.
+const MODELS_WITHOUT_THINKING: &[&str] = &[
+    "claude-3-haiku",
+    "claude-3-sonnet",
+    "claude-3-opus",
+    "claude-3-5-haiku",
+    "claude-3-5-sonnet",
+    "claude-2",
+    "claude-instant",
+];
+
+/// Whether `model` accepts extended thinking. Unknown names are assumed to,
+/// since every model since Claude 3.7 Sonnet does.
+pub fn supports_thinking(model: &str) -> bool {
+    !MODELS_WITHOUT_THINKING.iter().any(|prefix| model.starts_with(prefix))
+}
+
+pub const DEFAULT_MODEL: &str = "claude-sonnet-4-5-20250929";
+
+/// Output budget for the visible answer, on top of any thinking budget.
+pub const DEFAULT_MAX_TOKENS: u32 = 8000;
+
+pub const DEFAULT_SYSTEM_PROMPT: &str = "\
+You are Forge, an advanced AI coding agent with access to file system, shell and web tools.
+
+Your behavior:
+- You maintain full conversation context across all interactions
+- When asked for summaries, you reference previous actions and results
+- You think step-by-step about problems before acting
+- When a tool fails, you analyze what went wrong and try a different approach
+- You are concise but thorough
+- You execute tasks autonomously without asking for permission unless truly ambiguous
+- For work with three or more steps, you keep a task list with the todo tool and update it as you go
+
+Current working directory is preserved across commands.";
+
 #[derive(Serialize)]
 struct AnthropicRequest {
     model: String,
     max_tokens: u32,
     system: String,
     messages: Vec<Message>,
+    tools: Vec<ToolDefinition>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<Value>,
 }
 
-#[derive(Serialize, Clone)]
-struct Message {
-    role: String,
-    content: String,
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Message {
+    pub role: String,
+    pub content: Vec<ContentBlock>,
 }
 
-#[derive(Deserialize)]
-struct AnthropicResponse {
-    content: Vec<Content>,
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        is_error: bool,
+    },
+    /// Extended thinking. Must be sent back unchanged, signature included,
+    /// for the API to accept the following tool results.
+    Thinking {
+        thinking: String,
+        signature: String,
+    },
+    RedactedThinking {
+        data: String,
+    },
 }
 
 #[derive(Deserialize)]
-struct Content {
-    text: String,
+struct AnthropicResponse {
+    content: Vec<ContentBlock>,
+    stop_reason: Option<String>,
+    #[serde(default)]
+    usage: Usage,
 }
 
 pub struct Agent {
     client: Client,
     api_key: String,
-    output: Output,
+    provider: Provider,
+    model: String,
+    max_tokens: u32,
+    events: Arc<dyn EventSink>,
     system_prompt: String,
     messages: Vec<Message>,
+    limits: AgentLimits,
+    retry: RetryPolicy,
+    tool_definitions: Vec<ToolDefinition>,
+    http_cache: HttpCache,
+    egress: EgressPolicy,
+    interactive: bool,
+    ask_default: Option<String>,
+    permission_mode: PermissionMode,
+    approver: Option<Approver>,
+    custom_tools: Vec<Arc<dyn Tool>>,
+    /// Where tools run and what file tools may touch without asking.
+    workspace: Workspace,
+    outside_access: OutsideAccess,
+    /// Confines `bash`; `None` runs commands with Forge's own privileges.
+    sandbox: Option<Sandbox>,
+    /// Strips secrets from tool results and saved sessions.
+    redactor: Redactor,
+    audit: Option<AuditLog>,
+    trace: CallTrace,
+    hooks: Hooks,
+    /// `FORGE.md` files whose contents are in the system prompt.
+    instruction_files: Vec<PathBuf>,
+    /// Tool calls that ran in the current request.
+    completed: Vec<CompletedTool>,
+    session: Session,
+    interrupt: Interrupt,
+    prices: PriceTable,
+    /// Usage of the request currently being processed.
+    request_usage: Usage,
+    /// Text of the latest reply in the current request.
+    final_text: String,
+    budget: Budget,
+    thinking: ThinkingSettings,
 }
 
 impl Agent {
     pub fn new(api_key: String) -> Self {
-        let system_prompt = "\
-You are Forge, an advanced AI coding agent with access to file system tools.
-
-Your capabilities:
-- read <path>: Read file contents
-- write <path> <content>: Write/create files
-- edit <path> <search> <replace>: Edit files
-- bash <command>: Execute shell commands
-- glob <pattern>: Find files by pattern
-- grep <pattern> <path>: Search file contents
-
-Your behavior:
-- You maintain full conversation context across all interactions
-- When asked for summaries, you reference previous actions and results
-- You think step-by-step about problems before acting
-- When you encounter errors, you analyze what went wrong and try different approaches
-- You are concise but thorough
-- You execute tasks autonomously without asking for permission unless truly ambiguous
-
-Current working directory is preserved across commands.".to_string();
-
         Self {
             client: Client::new(),
             api_key,
-            output: Output::new(),
-            system_prompt,
+            provider: Provider::default(),
+            model: DEFAULT_MODEL.to_string(),
+            max_tokens: DEFAULT_MAX_TOKENS,
+            events: Arc::new(Output::new()),
+            system_prompt: DEFAULT_SYSTEM_PROMPT.to_string(),
             messages: Vec::new(),
+            limits: AgentLimits::default(),
+            retry: RetryPolicy::default(),
+            tool_definitions: tools::definitions(),
+            http_cache: HttpCache::default(),
+            egress: EgressPolicy::default(),
+            interactive: std::io::stdin().is_terminal(),
+            ask_default: None,
+            permission_mode: PermissionMode::default(),
+            approver: None,
+            custom_tools: Vec::new(),
+            workspace: Workspace::current().unwrap_or_else(|_| Workspace::detached()),
+            outside_access: OutsideAccess::default(),
+            sandbox: None,
+            redactor: Redactor::default(),
+            audit: None,
+            trace: CallTrace::default(),
+            hooks: Hooks::default(),
+            instruction_files: Vec::new(),
+            completed: Vec::new(),
+            session: Session {
+                id: Session::new_id(),
+                created_at: session::now(),
+                updated_at: 0,
+                messages: Vec::new(),
+                todos: Vec::new(),
+                usage: Usage::default(),
+                api_calls: 0,
+                cost_usd: None,
+            },
+            interrupt: Interrupt::default(),
+            prices: PriceTable::default(),
+            request_usage: Usage::default(),
+            final_text: String::new(),
+            budget: Budget::default(),
+            thinking: ThinkingSettings::default(),
         }
     }
 
-    pub fn add_user_message(&mut self, content: &str) {
-        self.messages.push(Message {
-            role: "user".to_string(),
-            content: content.to_string(),
-        });
+    /// Starts an `AgentBuilder`, the checked way to configure an agent from
+    /// library code.
+    pub fn builder(api_key: impl Into<String>) -> AgentBuilder {
+        AgentBuilder::new(api_key)
     }
 
-    pub async fn process(&mut self) -> Result<()> {
-        const MAX_TURNS: u32 = 5;
+    pub fn with_limits(mut self, limits: AgentLimits) -> Self {
+        self.limits = limits;
+        self
+    }
 
-        for turn in 1..=MAX_TURNS {
-            if turn > 1 {
-                self.output.tool_header(&format!("Turn {}", turn));
-                println!();
-            }
+    pub fn with_provider(mut self, provider: Provider) -> Self {
+        self.provider = provider;
+        self
+    }
 
-            // Step 1: Think and plan
-            self.output.tool_header("Thinking");
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
 
-            let plan_prompt = if turn == 1 {
-                "\
-Based on the conversation above, think step-by-step:
-1. What is the user asking for?
-2. What tools do I need to use?
-3. What's my approach?
+    /// Output token limit for the visible answer. Any thinking budget is
+    /// added on top.
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
 
-Then output tool calls as a JSON array at the end.
+    /// Replaces the built-in system prompt.
+    pub fn with_system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.system_prompt = prompt.into();
+        self
+    }
 
-Available tools:
-- read <path>: Read a file
-- write <path> <content>: Write a file
-- edit <path> <search> <replace>: Edit a file
-- bash <command>: Run shell command
-- glob <pattern>: Find files
-- grep <pattern> <path>: Search contents
+    /// Adds `extra` to the end of the current system prompt.
+    pub fn append_system_prompt(mut self, extra: &str) -> Self {
+        self.system_prompt = format!("{}\n\n{}", self.system_prompt.trim_end(), extra.trim());
+        self
+    }
 
-Format your response as:
-[Your reasoning here]
+    /// Offers `tool` to the model, replacing any tool with the same name.
+    pub fn with_tool(mut self, tool: Arc<dyn Tool>) -> Self {
+        let definition = tool.definition();
+        self.tool_definitions.retain(|d| d.name != definition.name);
+        self.custom_tools.retain(|t| t.definition().name != definition.name);
+        self.tool_definitions.push(definition);
+        self.custom_tools.push(tool);
+        self
+    }
 
-TOOL_CALLS:
-[{\"tool\": \"read\", \"args\": [\"path\"], \"description\": \"what this does\"}]
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
 
-If no tools are needed (e.g., just answering a question), respond with your answer and end with:
-TOOL_CALLS:
-[]"
-            } else {
-                "\
-Based on the tool results above, think step-by-step:
-1. Did the previous tools give me what I need?
-2. Is the task complete, or do I need to do more?
-3. If more work is needed, what tools should I use next?
-
-Available tools:
-- read <path>: Read a file
-- write <path> <content>: Write a file
-- edit <path> <search> <replace>: Edit a file
-- bash <command>: Run shell command
-- glob <pattern>: Find files
-- grep <pattern> <path>: Search contents
-
-Format:
-[Your reasoning - explain if task is complete or what to do next]
-
-TOOL_CALLS:
-[{\"tool\": \"read\", \"args\": [\"path\"], \"description\": \"what this does\"}]
-
-If task is complete, end with:
-TOOL_CALLS:
-[]"
-            };
+    /// Confines file tools to `workspace` and runs commands in its root.
+    /// Defaults to Forge's working directory.
+    pub fn with_workspace(mut self, workspace: Workspace) -> Self {
+        self.workspace = workspace;
+        self
+    }
+
+    /// Whether file tool calls outside the workspace are refused or need
+    /// approval. They need approval by default.
+    pub fn with_outside_access(mut self, access: OutsideAccess) -> Self {
+        self.outside_access = access;
+        self
+    }
 
-            let response = self.call_api(plan_prompt).await?;
+    /// Runs `bash` commands in `sandbox`. Commands may ask to escalate out
+    /// of it, which always needs approval.
+    pub fn with_sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
 
-            // Parse response to extract tool calls
-            let (reasoning, tool_calls) = self.parse_response(&response)?;
+    /// Records every tool call in `log`.
+    pub fn with_audit_log(mut self, log: AuditLog) -> Self {
+        self.audit = Some(log);
+        self
+    }
 
-            println!("{}", reasoning);
-            println!();
+    /// Commands to run before and after tool calls, on each prompt and
+    /// when the model stops.
+    pub fn with_hooks(mut self, hooks: Hooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
 
-            if tool_calls.is_empty() {
-                self.output.info("Task complete");
-                return Ok(());
-            }
+    /// Replaces the default redactor. The API key is always redacted too.
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
 
-            // Step 2: Execute tool calls
-            self.output.tool_header("Executing");
-            for (i, call) in tool_calls.iter().enumerate() {
-                self.output.list_item(i + 1, &format!("{} {}", call.tool, call.args.join(" ")));
-            }
-            println!();
+    /// Hides the named tools from the model and refuses to run them.
+    pub fn with_disabled_tools(mut self, names: &[String]) -> Self {
+        self.tool_definitions.retain(|d| !names.contains(&d.name));
+        self
+    }
 
-            self.execute_tool_calls(tool_calls).await?;
-            println!();
-        }
+    /// Where events go. Defaults to the terminal renderer; pass a
+    /// `NullSink`, a channel sender or a closure to embed the agent quietly.
+    pub fn with_event_sink(mut self, sink: impl EventSink + 'static) -> Self {
+        self.events = Arc::new(sink);
+        self
+    }
 
-        self.output.info(&format!("Reached max turns ({})", MAX_TURNS));
-        Ok(())
+    pub fn with_price_table(mut self, prices: PriceTable) -> Self {
+        self.prices = prices;
+        self
     }
 
-    fn parse_response(&self, response: &str) -> Result<(String, Vec<ToolCall>)> {
-        if let Some(split_pos) = response.find("TOOL_CALLS:") {
-            let reasoning = response[..split_pos].trim().to_string();
-            let json_part = response[split_pos + 11..].trim();
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = budget;
+        self
+    }
 
-            let cleaned_json = json_part
-                .strip_prefix("```json")
-                .or_else(|| json_part.strip_prefix("```"))
-                .unwrap_or(json_part)
-                .strip_suffix("```")
-                .unwrap_or(json_part)
-                .trim();
+    pub fn with_thinking(mut self, thinking: ThinkingSettings) -> Self {
+        self.thinking = thinking;
+        self
+    }
 
-            let tool_calls: Vec<ToolCall> = serde_json::from_str(cleaned_json)
-                .unwrap_or_else(|e| {
-                    eprintln!("Failed to parse tool calls: {}. JSON was:\n{}", e, cleaned_json);
-                    vec![]
-                });
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
 
-            Ok((reasoning, tool_calls))
-        } else {
-            Ok((response.to_string(), vec![]))
+    /// Lets Ctrl-C cancel the turn in flight. Without it `process` can't be
+    /// interrupted.
+    pub fn with_interrupt(mut self, interrupt: Interrupt) -> Self {
+        self.interrupt = interrupt;
+        self
+    }
+
+    pub fn with_http_cache(mut self, cache: HttpCache) -> Self {
+        self.http_cache = cache;
+        self
+    }
+
+    pub fn with_egress_policy(mut self, policy: EgressPolicy) -> Self {
+        self.egress = policy;
+        self
+    }
+
+    /// Controls whether `ask` may prompt. When `interactive` is false the
+    /// tool answers with `default_answer`, or reports that no user is
+    /// available.
+    pub fn with_ask_fallback(mut self, interactive: bool, default_answer: Option<String>) -> Self {
+        self.interactive = interactive;
+        self.ask_default = default_answer;
+        self
+    }
+
+    pub fn with_permission_mode(mut self, mode: PermissionMode) -> Self {
+        self.permission_mode = mode;
+        self
+    }
+
+    /// Adds loaded `FORGE.md` files to the end of the current system prompt.
+    pub fn with_instructions(mut self, instructions: &Instructions) -> Self {
+        if let Some(prompt) = instructions.prompt() {
+            self = self.append_system_prompt(&prompt);
         }
+        self.instruction_files.extend(instructions.files.iter().map(|f| f.path.clone()));
+        self
     }
 
-    fn handle_error<'a>(&'a mut self, error: &'a str, failed_tool: &'a ToolCall, depth: u32) -> Pin<Box<dyn Future<Output = Result<()>> + 'a>> {
-        Box::pin(async move {
-            self.output.tool_header("Error Recovery");
+    /// Answers approval requests instead of prompting on the terminal.
+    pub fn with_approver(mut self, approver: impl Fn(&ToolCall) -> bool + Send + Sync + 'static) -> Self {
+        self.approver = Some(Arc::new(approver));
+        self
+    }
 
-            // Add error context to conversation
-            self.add_user_message(&format!(
-                "ERROR: The tool call '{}' with args {:?} failed with error: {}\n\n\
-                Please analyze why this failed and try a different approach.",
-                failed_tool.tool, failed_tool.args, error
-            ));
+    pub fn session_id(&self) -> &str {
+        &self.session.id
+    }
 
-            // Process with error context using unified flow
-            self.process_with_retry(depth + 1).await?;
+    pub fn todos(&self) -> &[TodoItem] {
+        &self.session.todos
+    }
 
-            Ok(())
-        })
+    pub fn model(&self) -> &str {
+        &self.model
     }
 
-    async fn process_with_retry(&mut self, depth: u32) -> Result<()> {
-        const MAX_RETRIES: u32 = 2;
+    pub fn workspace(&self) -> &Workspace {
+        &self.workspace
+    }
 
-        if depth > MAX_RETRIES {
-            self.output.error("Max retries reached");
-            return Ok(());
-        }
+    /// Instruction files in the system prompt, in the order they appear.
+    pub fn instruction_files(&self) -> &[PathBuf] {
+        &self.instruction_files
+    }
+
+    /// Tokens used by the last request.
+    pub fn request_usage(&self) -> &Usage {
+        &self.request_usage
+    }
+
+    /// Cost of the last request in USD, if the model has a known price.
+    pub fn request_cost(&self) -> Option<f64> {
+        self.prices.cost(&self.model, &self.request_usage)
+    }
+
+    /// Text of the model's last reply in the last request.
+    pub fn final_text(&self) -> &str {
+        &self.final_text
+    }
 
-        // Think and plan with error context
-        self.output.tool_header("Thinking");
+    /// Tokens used across the whole session.
+    pub fn usage(&self) -> &Usage {
+        &self.session.usage
+    }
 
-        let plan_prompt = "\
-Based on the conversation and error above, think step-by-step:
-1. Why did the previous approach fail?
-2. What should I try instead?
-3. What tools do I need?
+    /// Session cost in USD, if the model has a known price.
+    pub fn cost(&self) -> Option<f64> {
+        self.prices.cost(&self.model, &self.session.usage)
+    }
 
-Then output tool calls as a JSON array.
+    /// API calls made across the whole session.
+    pub fn api_calls(&self) -> u32 {
+        self.session.api_calls
+    }
 
-Available tools:
-- read <path>: Read a file
-- write <path> <content>: Write a file
-- edit <path> <search> <replace>: Edit a file
-- bash <command>: Run shell command
-- glob <pattern>: Find files
-- grep <pattern> <path>: Search contents
+    /// A copy of `message` for writing to disk. Thinking is kept as is,
+    /// since the API checks its signature when a session is resumed.
+    fn redact_message(&self, message: &Message) -> Message {
+        let redactor = self.redactor();
+        let content = message
+            .content
+            .iter()
+            .map(|block| match block {
+                ContentBlock::Text { text } => ContentBlock::Text {
+                    text: redactor.redact(text).into_owned(),
+                },
+                ContentBlock::ToolUse { id, name, input } => ContentBlock::ToolUse {
+                    id: id.clone(),
+                    name: name.clone(),
+                    input: redact_value(&redactor, input),
+                },
+                ContentBlock::ToolResult { tool_use_id, content, is_error } => ContentBlock::ToolResult {
+                    tool_use_id: tool_use_id.clone(),
+                    content: redactor.redact(content).into_owned(),
+                    is_error: *is_error,
+                },
+                other => other.clone(),
+            })
+            .collect();
+        Message {
+            role: message.role.clone(),
+            content,
+        }
+    }
 
-Format:
-[Your reasoning]
+    /// The configured redactor, plus the API key this agent was given.
+    fn redactor(&self) -> Redactor {
+        self.redactor.clone().with_secret("api-key", &self.api_key)
+    }
 
-TOOL_CALLS:
-[{\"tool\": \"read\", \"args\": [\"path\"], \"description\": \"what this does\"}]";
+    fn emit(&self, event: AgentEvent) {
+        self.events.emit(&event);
+    }
 
-        let response = self.call_api(plan_prompt).await?;
-        let (reasoning, tool_calls) = self.parse_response(&response)?;
+    /// Writes the conversation and task list to the session file.
+    pub fn save_session(&mut self) -> Result<PathBuf> {
+        self.session.messages = self.messages.iter().map(|m| self.redact_message(m)).collect();
+        self.session.cost_usd = self.cost();
+        self.session.save()
+    }
 
-        println!("{}", reasoning);
-        println!();
+    pub fn add_user_message(&mut self, content: &str) {
+        self.push_user_blocks(vec![ContentBlock::Text {
+            text: content.to_string(),
+        }]);
+    }
 
-        if tool_calls.is_empty() {
-            self.output.info("No alternative approach found");
-            return Ok(());
+    /// Appends to the trailing user message if there is one, so a stopped
+    /// run's tool results and the next prompt stay in a single user turn.
+    fn push_user_blocks(&mut self, blocks: Vec<ContentBlock>) {
+        match self.messages.last_mut() {
+            Some(last) if last.role == "user" => last.content.extend(blocks),
+            _ => self.messages.push(Message {
+                role: "user".to_string(),
+                content: blocks,
+            }),
         }
+    }
 
-        self.output.tool_header("Executing");
-        for (i, call) in tool_calls.iter().enumerate() {
-            self.output.list_item(i + 1, &format!("{} {}", call.tool, call.args.join(" ")));
+    /// Adds `prompt` to the conversation and runs the agent loop on it. If
+    /// the request fails before the model has answered at all, the prompt is
+    /// taken back out so the history is exactly as it was. A
+    /// `user_prompt_submit` hook may refuse the prompt or add to it.
+    pub async fn run(&mut self, prompt: &str) -> Result<AgentResult> {
+        let hooks = self
+            .run_hooks(HookEvent::UserPromptSubmit, None, serde_json::json!({ "prompt": prompt }))
+            .await;
+        if let Some(reason) = hooks.blocked {
+            return Err(anyhow::anyhow!("Prompt blocked by hook: {}", reason));
         }
-        println!();
 
-        self.execute_tool_calls_with_retry(tool_calls, depth).await?;
+        let len = self.messages.len();
+        let trailing_user_blocks = self.messages.last()
+            .filter(|m| m.role == "user")
+            .map(|m| m.content.len());
 
-        Ok(())
+        self.add_user_message(prompt);
+        if !hooks.context.is_empty() {
+            self.push_user_blocks(vec![ContentBlock::Text {
+                text: hooks.context.join("\n\n"),
+            }]);
+        }
+        let with_prompt = self.messages.len();
+        let result = self.process().await;
+
+        if result.is_err() && self.messages.len() == with_prompt {
+            self.messages.truncate(len);
+            if let (Some(blocks), Some(last)) = (trailing_user_blocks, self.messages.last_mut()) {
+                last.content.truncate(blocks);
+            }
+        }
+        Ok(AgentResult {
+            stop_reason: result?,
+            text: self.final_text.clone(),
+            tools: self.completed.clone(),
+            usage: self.request_usage,
+            cost_usd: self.request_cost(),
+        })
     }
 
-    async fn call_api(&mut self, prompt: &str) -> Result<String> {
-        // Add user message to history
-        self.messages.push(Message {
-            role: "user".to_string(),
-            content: prompt.to_string(),
-        });
+    /// Runs the agent loop for the pending user message: call the model,
+    /// execute any tools it asks for, feed the results back, and repeat
+    /// until it stops asking for tools or a budget runs out.
+    pub async fn process(&mut self) -> Result<StopReason> {
+        let cancel = self.interrupt.begin();
+        self.request_usage = Usage::default();
+        self.final_text.clear();
+        self.completed.clear();
+        let mut turns = 0;
+        let mut tool_calls = 0;
+        let mut warned = false;
+        let mut stop_hook_active = false;
+
+        loop {
+            // Checked between turns so a limit never cuts a tool off halfway.
+            match self.budget_status() {
+                BudgetStatus::Exceeded(detail) => {
+                    self.add_user_message(&format!("[Stopped: budget limit reached ({})]", detail));
+                    self.emit(AgentEvent::BudgetExceeded {
+                        detail,
+                        completed: self.completed.clone(),
+                        todos: self.session.todos.clone(),
+                    });
+                    return Ok(StopReason::BudgetExceeded);
+                }
+                BudgetStatus::Warning(detail) if !warned => {
+                    warned = true;
+                    self.emit(AgentEvent::Warning {
+                        message: format!("Budget warning: {}", detail),
+                    });
+                }
+                _ => {}
+            }
+
+            if turns >= self.limits.max_turns {
+                return Ok(self.stop(
+                    StopReason::MaxTurns,
+                    format!("Stopped: reached max turns ({})", self.limits.max_turns),
+                ));
+            }
+            turns += 1;
+
+            let response = tokio::select! {
+                response = self.call_api() => response?,
+                _ = cancel.cancelled() => {
+                    self.add_user_message(INTERRUPTED_MARKER);
+                    return Ok(self.stop(StopReason::Interrupted, "Interrupted".to_string()));
+                }
+            };
+
+            let mut calls = Vec::new();
+            self.final_text = response.content.iter()
+                .filter_map(|block| match block {
+                    ContentBlock::Text { text } => Some(text.trim()),
+                    _ => None,
+                })
+                .filter(|text| !text.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n\n");
+            for block in &response.content {
+                match block {
+                    ContentBlock::Thinking { thinking, .. } => self.emit(AgentEvent::Thinking {
+                        text: thinking.trim().to_string(),
+                        redacted: false,
+                    }),
+                    ContentBlock::RedactedThinking { .. } => self.emit(AgentEvent::Thinking {
+                        text: String::new(),
+                        redacted: true,
+                    }),
+                    ContentBlock::Text { text } if !text.trim().is_empty() => {
+                        self.emit(AgentEvent::AssistantText {
+                            text: text.trim().to_string(),
+                        });
+                    }
+                    ContentBlock::ToolUse { id, name, input } => calls.push(ToolCall {
+                        id: id.clone(),
+                        tool: name.clone(),
+                        input: input.clone(),
+                    }),
+                    _ => {}
+                }
+            }
+
+            if calls.is_empty() {
+                if response.stop_reason.as_deref() == Some("max_tokens") {
+                    return Ok(self.stop(StopReason::MaxTokens, "Stopped: response hit the output token limit".to_string()));
+                }
+                // A `stop` hook that blocks sends the model back to work,
+                // e.g. until the tests pass; `max_turns` still applies.
+                let payload = serde_json::json!({
+                    "final_text": self.final_text,
+                    "stop_hook_active": stop_hook_active,
+                });
+                match self.run_hooks(HookEvent::Stop, None, payload).await.blocked {
+                    Some(reason) => {
+                        stop_hook_active = true;
+                        self.add_user_message(&format!("[Stop hook: {}]", reason));
+                        continue;
+                    }
+                    None => return Ok(StopReason::Completed),
+                }
+            }
+
+            let mut results = Vec::with_capacity(calls.len());
+            let mut interrupted = false;
+            while results.len() < calls.len() && !interrupted {
+                let next = results.len();
+                let budget = (self.limits.max_tool_calls - tool_calls) as usize;
+                if budget == 0 {
+                    break;
+                }
 
+                // Consecutive read-only calls run together; anything with
+                // side effects, or that may need approval to leave the
+                // workspace, runs on its own, in order.
+                let batchable = |c: &ToolCall| {
+                    self.is_read_only(&c.tool) && self.outside_path(c).is_none() && !self.has_tool_hooks(c)
+                };
+                let batch_len = if batchable(&calls[next]) {
+                    calls[next..].iter().take_while(|c| batchable(c)).count()
+                } else {
+                    1
+                }
+                .min(budget);
+                let batch = &calls[next..next + batch_len];
+                tool_calls += batch_len as u32;
+
+                if batch_len == 1 {
+                    self.tool_start(next + 1, calls.len(), &batch[0]);
+                    let started = Instant::now();
+                    // Dropping the tool future on cancel also kills any
+                    // process group `bash` started.
+                    tokio::select! {
+                        outcome = self.execute_with_hooks(&batch[0]) => {
+                            results.push(self.report(&batch[0], outcome, started.elapsed()));
+                        }
+                        _ = cancel.cancelled() => {
+                            interrupted = true;
+                            self.audit(&batch[0], self.trace_decision(None), AuditStatus::Interrupted, None, started.elapsed());
+                        }
+                    }
+                } else {
+                    self.trace = CallTrace::default();
+                    for (i, call) in batch.iter().enumerate() {
+                        self.tool_start(next + i + 1, calls.len(), call);
+                    }
+                    let started = Instant::now();
+                    let mut outcomes = stream::iter(batch)
+                        .map(|call| timed(self.execute_read_only_tool(call)))
+                        .buffered(self.limits.max_parallel_tools.max(1));
+                    loop {
+                        tokio::select! {
+                            outcome = outcomes.next() => {
+                                let Some((outcome, elapsed)) = outcome else { break };
+                                let call = &calls[results.len()];
+                                results.push(self.report(call, outcome, elapsed));
+                            }
+                            _ = cancel.cancelled() => {
+                                interrupted = true;
+                                // Dropping the stream cancels the calls still in flight.
+                                for call in &calls[results.len()..next + batch_len] {
+                                    self.audit(call, self.trace_decision(None), AuditStatus::Interrupted, None, started.elapsed());
+                                }
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+
+            for (call, result) in calls.iter().zip(&results) {
+                if let ContentBlock::ToolResult { is_error, .. } = result {
+                    self.completed.push(CompletedTool {
+                        summary: call.summary(),
+                        is_error: *is_error,
+                    });
+                }
+            }
+
+            // Every tool_use needs a matching tool_result, even the ones we
+            // never ran.
+            let skipped = if interrupted {
+                "Not executed: interrupted by user"
+            } else {
+                "Not executed: tool-call budget exhausted"
+            };
+            let budget_exhausted = !interrupted && results.len() < calls.len();
+            for call in &calls[results.len()..] {
+                results.push(ContentBlock::ToolResult {
+                    tool_use_id: call.id.clone(),
+                    content: skipped.to_string(),
+                    is_error: true,
+                });
+            }
+
+            self.push_user_blocks(results);
+
+            if interrupted {
+                self.add_user_message(INTERRUPTED_MARKER);
+                return Ok(self.stop(StopReason::Interrupted, "Interrupted".to_string()));
+            }
+
+            if budget_exhausted {
+                return Ok(self.stop(
+                    StopReason::MaxToolCalls,
+                    format!("Stopped: reached max tool calls ({})", self.limits.max_tool_calls),
+                ));
+            }
+        }
+    }
+
+    /// The Messages API request for the conversation so far.
+    fn request(&self) -> Result<Value> {
+        let thinking = self.thinking.enabled && supports_thinking(&self.model);
+        let thinking_budget = self.thinking.budget_tokens.max(1024);
         let request = AnthropicRequest {
-            model: "claude-sonnet-4-5-20250929".to_string(),
-            max_tokens: 8000,
+            model: self.model.clone(),
+            max_tokens: if thinking {
+                self.max_tokens + thinking_budget
+            } else {
+                self.max_tokens
+            },
             system: self.system_prompt.clone(),
             messages: self.messages.clone(),
+            tools: self.tool_definitions.clone(),
+            thinking: thinking.then(|| serde_json::json!({
+                "type": "enabled",
+                "budget_tokens": thinking_budget,
+            })),
+        };
+        let mut request = serde_json::to_value(&request)?;
+        if self.provider.caching_enabled() {
+            provider::add_cache_breakpoints(&mut request);
+        }
+        Ok(request)
+    }
+
+    /// Sends the conversation to the API, retrying transient failures with
+    /// exponential backoff. History is only touched once a call succeeds.
+    async fn call_api(&mut self) -> Result<AnthropicResponse> {
+        let request = self.request()?;
+
+        let mut attempt = 0;
+        let response_body = loop {
+            match self.send_request(&request).await {
+                Ok(body) => break body,
+                Err(e) if e.is_retryable() && attempt < self.retry.max_retries => {
+                    attempt += 1;
+                    let delay = self.retry.delay(attempt, e.retry_after());
+                    self.emit(AgentEvent::Warning {
+                        message: format!(
+                            "{} (retry {}/{} in {:.1}s)",
+                            e,
+                            attempt,
+                            self.retry.max_retries,
+                            delay.as_secs_f32()
+                        ),
+                    });
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e.into()),
+            }
         };
 
+        self.request_usage += response_body.usage;
+        self.session.usage += response_body.usage;
+        self.session.api_calls += 1;
+        self.emit(AgentEvent::Usage {
+            usage: response_body.usage,
+            cost_usd: self.prices.cost(&self.model, &response_body.usage),
+        });
+
+        // Add assistant response to history
+        self.messages.push(Message {
+            role: "assistant".to_string(),
+            content: response_body.content.clone(),
+        });
+
+        Ok(response_body)
+    }
+
+    async fn send_request(&self, request: &Value) -> Result<AnthropicResponse, ApiError> {
         let response = self.client
-            .post("https://api.anthropic.com/v1/messages")
+            .post(self.provider.messages_url())
             .header("x-api-key", &self.api_key)
             .header("anthropic-version", "2023-06-01")
             .header("content-type", "application/json")
-            .json(&request)
+            .json(request)
             .send()
-            .await?;
+            .await
+            .map_err(ApiError::Connection)?;
+
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_retry_after);
+        let body = response.text().await.map_err(ApiError::Connection)?;
+
+        if !status.is_success() {
+            return Err(ApiError::from_response(status.as_u16(), retry_after, &body));
+        }
+        serde_json::from_str(&body).map_err(|e| ApiError::Decode(e.to_string()))
+    }
 
-        let response_body: AnthropicResponse = response.json().await?;
+    fn budget_status(&self) -> BudgetStatus {
+        self.budget.check(
+            &self.request_usage,
+            self.prices.cost(&self.model, &self.request_usage),
+            &self.session.usage,
+            self.cost(),
+        )
+    }
 
-        let assistant_message = response_body.content.first()
-            .map(|c| c.text.clone())
-            .unwrap_or_else(|| "No response".to_string());
+    /// Reports an early stop and passes the reason through.
+    fn stop(&self, reason: StopReason, message: String) -> StopReason {
+        self.emit(AgentEvent::Stopped { reason, message });
+        reason
+    }
 
-        // Add assistant response to history
-        self.messages.push(Message {
-            role: "assistant".to_string(),
-            content: assistant_message.clone(),
+    fn tool_start(&self, index: usize, total: usize, call: &ToolCall) {
+        self.emit(AgentEvent::ToolStart {
+            id: call.id.clone(),
+            tool: call.tool.clone(),
+            input: call.input.clone(),
+            summary: call.summary(),
+            classification: self.classify(call),
+            index,
+            total,
         });
+    }
 
-        Ok(assistant_message)
+    /// The static classification of a built-in `bash` call's command.
+    fn classify(&self, call: &ToolCall) -> Option<Classification> {
+        if call.tool != "bash" || self.custom_tool(&call.tool).is_some() {
+            return None;
+        }
+        call.input.get("command").and_then(Value::as_str).map(shell::classify)
     }
 
-    pub async fn execute_tool_calls(&mut self, tool_calls: Vec<ToolCall>) -> Result<()> {
-        self.execute_tool_calls_with_retry(tool_calls, 0).await
+    fn custom_tool(&self, name: &str) -> Option<&Arc<dyn Tool>> {
+        self.custom_tools.iter().find(|t| t.definition().name == name)
     }
 
-    async fn execute_tool_calls_with_retry(&mut self, tool_calls: Vec<ToolCall>, depth: u32) -> Result<()> {
-        const MAX_RETRIES: u32 = 2;
+    /// `path` as the tools should open it: relative paths are taken from the
+    /// workspace root.
+    fn resolve(&self, path: &str) -> String {
+        self.workspace.resolve(path).display().to_string()
+    }
 
-        self.output.tool_header("Executing");
+    /// The real path a built-in file tool call would touch, if that is
+    /// outside the workspace. Paths that can't be resolved are left for the
+    /// tool itself to report.
+    fn outside_path(&self, call: &ToolCall) -> Option<String> {
+        if !FILE_TOOLS.contains(&call.tool.as_str()) || self.custom_tool(&call.tool).is_some() {
+            return None;
+        }
+        let path = call.input.get("path").and_then(Value::as_str).unwrap_or(".");
+        match self.workspace.check(path) {
+            Err(ToolError::OutsideWorkspace(real)) => Some(real),
+            _ => None,
+        }
+    }
 
-        let mut results = Vec::new();
-        let mut had_error = false;
-        let mut failed_call: Option<ToolCall> = None;
-        let mut error_msg = String::new();
+    /// Asks the approver, or the user, whether `call` may run.
+    fn approve(&mut self, call: &ToolCall, summary: &str) -> Result<bool> {
+        let approved = match &self.approver {
+            Some(approver) => approver(call),
+            None => permission::confirm(summary, self.interactive)?,
+        };
+        self.trace.approved |= approved;
+        Ok(approved)
+    }
 
-        for (i, call) in tool_calls.iter().enumerate() {
-            self.output.info(&format!("\n[{}/{}] {}", i + 1, tool_calls.len(), call.description));
+    /// The sandbox a `bash` call runs in, once any escalation it asks for
+    /// has been approved.
+    fn sandbox_for(&mut self, call: &ToolCall) -> Result<Option<Sandbox>> {
+        let Some(sandbox) = &self.sandbox else {
+            return Ok(None);
+        };
+        let (sandbox, detail) = match call.input.get("escalate").and_then(Value::as_str) {
+            None => return Ok(Some(sandbox.clone())),
+            Some("network") => (Some(sandbox.clone().with_network()), "with network access"),
+            Some("unsandboxed") => (None, "without the sandbox"),
+            Some(other) => return Err(anyhow::anyhow!("Unknown escalation: {}", other)),
+        };
+        let summary = format!("{} ({})", call.summary(), detail);
+        if !self.approve(call, &summary)? {
+            return Err(ToolError::Rejected(summary).into());
+        }
+        Ok(sandbox)
+    }
 
-            match self.execute_single_tool(&call).await {
-                Ok(result) => {
-                    if !result.is_empty() {
-                        println!("{}", result);
-                    }
-                    self.output.success("✓ Done");
-                    results.push(format!("{}: {}", call.description, result));
-                }
-                Err(e) => {
-                    let err_str = format!("{}", e);
-                    self.output.error(&format!("✗ Error: {}", err_str));
-                    results.push(format!("{}: Error - {}", call.description, err_str));
-
-                    if depth < MAX_RETRIES {
-                        had_error = true;
-                        failed_call = Some(call.clone());
-                        error_msg = err_str;
-                        break;
-                    }
-                }
-            }
+    /// Filters the results of a search under `base`: a path a symlink led
+    /// out of the workspace maps to `None`, and the rest are shortened for
+    /// display. A search approved to run outside the workspace keeps all.
+    fn confine(&self, base: &str) -> impl Fn(&str) -> Option<String> + '_ {
+        let base_inside = self.workspace.check(base).is_ok();
+        move |path| {
+            (!base_inside || self.workspace.check(path).is_ok()).then(|| self.workspace.display(path))
         }
+    }
 
-        println!();
+    /// Refuses `path` if it matches `redaction.deny_read`.
+    fn check_readable(&self, path: &str) -> Result<(), ToolError> {
+        if self.read_denied(path) {
+            Err(ToolError::DeniedRead(path.to_string()))
+        } else {
+            Ok(())
+        }
+    }
 
-        // Add tool execution results to conversation history
-        if !results.is_empty() {
-            let results_summary = format!("Tool execution results:\n{}", results.join("\n"));
-            self.messages.push(Message {
-                role: "user".to_string(),
-                content: results_summary,
+    /// Whether `path`, or the file a symlink at it points to, matches
+    /// `redaction.deny_read`.
+    fn read_denied(&self, path: &str) -> bool {
+        let resolved = self.workspace.resolve(path);
+        let real = resolved.canonicalize().unwrap_or_else(|_| resolved.clone());
+        [&resolved, &real]
+            .iter()
+            .any(|p| self.redactor.denies_read(p.strip_prefix(self.workspace.root()).unwrap_or(p)))
+    }
+
+    fn is_read_only(&self, tool: &str) -> bool {
+        self.tool_definitions.iter().any(|d| d.name == tool && d.read_only)
+    }
+
+    /// Reports the outcome of a tool call, records it in the audit log and
+    /// turns it into a `tool_result`.
+    fn report(&self, call: &ToolCall, outcome: Result<String>, elapsed: Duration) -> ContentBlock {
+        let decision = self.trace_decision(outcome.as_ref().err());
+        let (content, is_error) = match outcome {
+            Ok(result) => (result, false),
+            Err(e) => (format!("{}", e), true),
+        };
+        let content = self.redactor().redact(&content).into_owned();
+        let status = if is_error { AuditStatus::Error } else { AuditStatus::Ok };
+        self.audit(call, decision, status, Some(&content), elapsed);
+        self.emit(AgentEvent::ToolResult {
+            id: call.id.clone(),
+            tool: call.tool.clone(),
+            content: content.clone(),
+            is_error,
+        });
+        ContentBlock::ToolResult {
+            tool_use_id: call.id.clone(),
+            content,
+            is_error,
+        }
+    }
+
+    /// How the permission checks treated the call that just ran, judging
+    /// by its error and whether anything was approved along the way.
+    fn trace_decision(&self, error: Option<&anyhow::Error>) -> AuditDecision {
+        match error.and_then(|e| e.downcast_ref::<ToolError>()) {
+            Some(ToolError::Rejected(_)) => AuditDecision::Rejected,
+            Some(
+                ToolError::Denied(_)
+                | ToolError::OutsideWorkspace(_)
+                | ToolError::DeniedRead(_)
+                | ToolError::HookBlocked(_),
+            ) => AuditDecision::Denied,
+            _ if self.trace.approved => AuditDecision::Approved,
+            _ => AuditDecision::Allowed,
+        }
+    }
+
+    /// Appends a call to the audit log, if there is one. A log that can't
+    /// be written is reported but doesn't stop the agent.
+    fn audit(
+        &self,
+        call: &ToolCall,
+        decision: AuditDecision,
+        status: AuditStatus,
+        output: Option<&str>,
+        elapsed: Duration,
+    ) {
+        let Some(log) = &self.audit else {
+            return;
+        };
+        let entry = AuditEntry {
+            timestamp: session::now().saturating_sub(elapsed.as_secs()),
+            session_id: self.session.id.clone(),
+            tool_use_id: call.id.clone(),
+            tool: call.tool.clone(),
+            input: redact_value(&self.redactor(), self.trace.input.as_ref().unwrap_or(&call.input)),
+            decision,
+            status,
+            exit_code: if call.tool == "bash" { self.trace.exit_code } else { None },
+            duration_ms: elapsed.as_millis() as u64,
+            output_sha256: output.map(AuditEntry::output_hash),
+            workspace: self.workspace.root().display().to_string(),
+        };
+        if let Err(e) = log.append(&entry) {
+            self.emit(AgentEvent::Warning {
+                message: format!("Cannot write audit log {}: {}", log.path().display(), e),
             });
         }
+    }
 
-        // If error occurred and we haven't hit max retries, try error recovery
-        if had_error {
-            if let Some(call) = failed_call {
-                println!();
-                self.output.info(&format!("Retry attempt {}/{}", depth + 1, MAX_RETRIES));
-                self.handle_error(&error_msg, &call, depth).await?;
-            }
-        } else {
-            self.output.success("All tasks completed!");
+    /// Whether a `pre_tool_use` or `post_tool_use` hook runs for `call`.
+    fn has_tool_hooks(&self, call: &ToolCall) -> bool {
+        let path = self.hook_path(call);
+        [HookEvent::PreToolUse, HookEvent::PostToolUse]
+            .into_iter()
+            .any(|event| self.hooks.matches(event, Some(&call.tool), path.as_deref()))
+    }
+
+    /// The call's `path` argument, relative to the workspace root when it
+    /// is inside it, for matching a hook's `paths`.
+    fn hook_path(&self, call: &ToolCall) -> Option<PathBuf> {
+        let path = self.workspace.resolve(call.input.get("path")?.as_str()?);
+        Some(path.strip_prefix(self.workspace.root()).map(Path::to_path_buf).unwrap_or(path))
+    }
+
+    /// Runs the hooks for `event`, with the session, the workspace and any
+    /// tool call added to `payload`, and reports hooks that failed.
+    async fn run_hooks(&self, event: HookEvent, call: Option<&ToolCall>, mut payload: Value) -> HookOutcome {
+        payload["session_id"] = Value::from(self.session.id.as_str());
+        payload["workspace"] = Value::from(self.workspace.root().display().to_string());
+        if let Some(call) = call {
+            payload["tool"] = Value::from(call.tool.as_str());
+            payload["tool_use_id"] = Value::from(call.id.as_str());
+            payload["input"] = call.input.clone();
+        }
+        let path = call.and_then(|c| self.hook_path(c));
+        let outcome = self
+            .hooks
+            .run(event, call.map(|c| c.tool.as_str()), path.as_deref(), payload, self.workspace.root())
+            .await;
+        for message in &outcome.warnings {
+            self.emit(AgentEvent::Warning {
+                message: message.clone(),
+            });
+        }
+        outcome
+    }
+
+    /// Runs a call between its `pre_tool_use` and `post_tool_use` hooks. A
+    /// pre hook may block the call or change its arguments, which then go
+    /// through the permission checks as usual; a post hook sees the
+    /// redacted result and may flag it as an error. Feedback from either
+    /// is added to the result.
+    async fn execute_with_hooks(&mut self, call: &ToolCall) -> Result<String> {
+        self.trace = CallTrace::default();
+        if !self.has_tool_hooks(call) {
+            return self.execute_single_tool(call).await;
+        }
+        let pre = self.run_hooks(HookEvent::PreToolUse, Some(call), Value::Null).await;
+        if let Some(reason) = pre.blocked {
+            return Err(ToolError::HookBlocked(reason).into());
         }
+        let modified = pre.input.map(|input| ToolCall {
+            input,
+            ..call.clone()
+        });
+        self.trace.input = modified.as_ref().map(|c| c.input.clone());
+        let call = modified.as_ref().unwrap_or(call);
+        let outcome = self.execute_single_tool(call).await;
 
-        Ok(())
+        let (output, is_error) = match &outcome {
+            Ok(output) => (output.clone(), false),
+            Err(e) => (format!("{}", e), true),
+        };
+        let payload = serde_json::json!({
+            "output": self.redactor().redact(&output),
+            "is_error": is_error,
+        });
+        let post = self.run_hooks(HookEvent::PostToolUse, Some(call), payload).await;
+
+        let mut feedback = pre.context;
+        feedback.extend(post.context);
+        feedback.extend(post.blocked.iter().map(|reason| format!("Blocked by hook: {}", reason)));
+        if feedback.is_empty() {
+            return outcome;
+        }
+        let text = format!("{}\n\n[Hook feedback]\n{}", output, feedback.join("\n"));
+        match outcome {
+            Ok(_) if post.blocked.is_none() => Ok(text),
+            Ok(_) => Err(anyhow::anyhow!(text)),
+            // Kept as the cause, so the audit log still sees why it failed.
+            Err(e) => Err(e.context(text)),
+        }
     }
 
-    async fn execute_single_tool(&self, call: &ToolCall) -> Result<String> {
-        match call.tool.as_str() {
-            "read" => {
-                let path = call.args.get(0).map(|s| s.as_str()).unwrap_or("");
-                let content = tools::read(path, None, None)?;
-                Ok(content)
+    async fn execute_single_tool(&mut self, call: &ToolCall) -> Result<String> {
+        if !self.tool_definitions.iter().any(|d| d.name == call.tool) {
+            return Err(anyhow::anyhow!("Unknown tool: {}", call.tool));
+        }
+        let classification = self.classify(call);
+        let decision = match &classification {
+            Some(command) => self.permission_mode.check_command(command),
+            None => self.permission_mode.check(&call.tool, self.is_read_only(&call.tool)),
+        };
+        match decision {
+            Decision::Allow => {}
+            Decision::Deny(reason) => return Err(ToolError::Denied(reason).into()),
+            Decision::Ask => {
+                let summary = match &classification {
+                    Some(command) => format!("{} ({})", call.summary(), command.describe()),
+                    None => call.summary(),
+                };
+                if !self.approve(call, &summary)? {
+                    return Err(ToolError::Rejected(summary).into());
+                }
             }
+        }
+        if let Some(path) = self.outside_path(call) {
+            match self.outside_access {
+                OutsideAccess::Deny => return Err(ToolError::OutsideWorkspace(path).into()),
+                OutsideAccess::Ask => {
+                    let summary = format!("{} {} (outside the workspace)", call.tool, path);
+                    if !self.approve(call, &summary)? {
+                        return Err(ToolError::Rejected(summary).into());
+                    }
+                }
+            }
+        }
+        if self.custom_tool(&call.tool).is_some() {
+            return self.execute_read_only_tool(call).await;
+        }
+        let input = &call.input;
+        match call.tool.as_str() {
             "write" => {
-                let path = call.args.get(0).map(|s| s.as_str()).unwrap_or("");
-                let content = call.args[1..].join(" ");
-                tools::write(path, &content)?;
+                let path = str_arg(input, "path")?;
+                let content = str_arg(input, "content")?;
+                tools::write(&self.resolve(path), content)?;
                 Ok(format!("Wrote to {}", path))
             }
             "edit" => {
-                let path = call.args.get(0).map(|s| s.as_str()).unwrap_or("");
-                let search = call.args.get(1).map(|s| s.as_str()).unwrap_or("");
-                let replace = call.args.get(2).map(|s| s.as_str()).unwrap_or("");
-                let diff = tools::edit(path, search, replace, false)?;
+                let path = str_arg(input, "path")?;
+                let search = str_arg(input, "old_string")?;
+                let replace = str_arg(input, "new_string")?;
+                self.check_readable(path)?;
+                let diff = tools::edit(&self.resolve(path), search, replace, bool_arg(input, "replace_all"))?;
                 Ok(format!("Edited {}\n{}", path, diff))
             }
             "bash" => {
-                let command = call.args.join(" ");
-                let result = tools::bash(&command).await?;
-                Ok(result.output)
+                let command = str_arg(input, "command")?;
+                let sandbox = self.sandbox_for(call)?;
+                let events = self.events.clone();
+                let redactor = self.redactor();
+                let mut stdout = LineRedactor::new(&redactor);
+                let mut stderr = LineRedactor::new(&redactor);
+                let emit = |stream, shown: String| {
+                    for line in shown.split('\n') {
+                        events.emit(&AgentEvent::ToolOutput {
+                            id: call.id.clone(),
+                            stream,
+                            line: line.to_string(),
+                        });
+                    }
+                };
+                let result = tools::bash(command, Some(self.workspace.root()), sandbox.as_ref(), |stream, line| {
+                    let lines = match stream {
+                        OutputStream::Stdout => &mut stdout,
+                        OutputStream::Stderr => &mut stderr,
+                    };
+                    if let Some(shown) = lines.push(line) {
+                        emit(stream, shown);
+                    }
+                })
+                .await;
+                for (stream, lines) in [(OutputStream::Stdout, &mut stdout), (OutputStream::Stderr, &mut stderr)] {
+                    if let Some(shown) = lines.finish() {
+                        emit(stream, shown);
+                    }
+                }
+                let result = result?;
+                self.trace.exit_code = result.exit_code;
+                let mut output = match result.exit_code {
+                    Some(0) => return Ok(result.output),
+                    Some(code) => format!("{}\n[exit code {}]", result.output, code),
+                    None => format!("{}\n[terminated by signal]", result.output),
+                };
+                // Tell the model, so it can tell a sandbox refusal from a bug.
+                if let Some(sandbox) = &sandbox {
+                    output.push_str(&format!("\n[sandboxed: {}]", sandbox.describe()));
+                }
+                Ok(output)
+            }
+            "ask" => {
+                let question = str_arg(input, "question")?;
+                let mode = input.get("mode").and_then(Value::as_str).unwrap_or("text");
+                let options: Option<Vec<String>> = input.get("options")
+                    .map(|o| serde_json::from_value(o.clone()))
+                    .transpose()?;
+                let options = options.filter(|o| !o.is_empty() && mode != "text");
+
+                let answer = tools::ask(
+                    question,
+                    options,
+                    mode == "multi",
+                    self.interactive,
+                    self.ask_default.as_deref(),
+                )?;
+                Ok(match answer {
+                    AskResult::Single(choice) => format!("User selected: {}", choice),
+                    AskResult::Multi(choices) => format!("User selected: {}", choices.join(", ")),
+                    AskResult::Text(text) => format!("User answered: {}", text),
+                    AskResult::Declined => "User declined to answer.".to_string(),
+                    AskResult::Unattended(Some(default)) => format!(
+                        "No user available (non-interactive run). Using the configured default answer: {}",
+                        default
+                    ),
+                    AskResult::Unattended(None) => "No user available (non-interactive run). \
+                        Make a reasonable assumption and state it, or stop and explain what input is needed."
+                        .to_string(),
+                })
+            }
+            "todo" => {
+                let todos = serde_json::from_value(input.get("todos").cloned().unwrap_or_default())?;
+                self.session.todos = tools::todo(todos)?;
+                self.emit(AgentEvent::Todos {
+                    todos: self.session.todos.clone(),
+                });
+                let count = |status| self.session.todos.iter().filter(|t| t.status == status).count();
+                Ok(format!(
+                    "Todo list updated: {} completed, {} in progress, {} pending",
+                    count(TodoStatus::Completed),
+                    count(TodoStatus::InProgress),
+                    count(TodoStatus::Pending)
+                ))
+            }
+            _ => self.execute_read_only_tool(call).await,
+        }
+    }
+
+    /// Tools without side effects. Takes `&self` so several can run at once;
+    /// blocking file-system work is moved off the async workers.
+    async fn execute_read_only_tool(&self, call: &ToolCall) -> Result<String> {
+        if let Some(tool) = self.custom_tool(&call.tool) {
+            return tool.call(call.input.clone()).await;
+        }
+        let input = &call.input;
+        match call.tool.as_str() {
+            "read" => {
+                self.check_readable(str_arg(input, "path")?)?;
+                let path = self.resolve(str_arg(input, "path")?);
+                let (offset, limit) = (usize_arg(input, "offset"), usize_arg(input, "limit"));
+                task::spawn_blocking(move || tools::read(&path, offset, limit)).await?
             }
             "glob" => {
-                let pattern = call.args.get(0).map(|s| s.as_str()).unwrap_or("");
-                let matches = tools::glob(pattern, None)?;
+                let pattern = str_arg(input, "pattern")?.to_string();
+                let path = input.get("path").and_then(Value::as_str).unwrap_or(".");
+                let base = self.resolve(path);
+                let matches = task::spawn_blocking(move || tools::glob(&pattern, Some(&base))).await??;
+                let keep = self.confine(path);
+                let matches: Vec<String> = matches.iter().filter_map(|m| keep(m)).collect();
                 Ok(format!("Found {} files:\n{}", matches.len(), matches.join("\n")))
             }
             "grep" => {
-                let pattern = call.args.get(0).map(|s| s.as_str()).unwrap_or("");
-                let path = call.args.get(1).map(|s| s.as_str()).unwrap_or(".");
-                let matches = tools::grep(pattern, path, false)?;
+                let pattern = str_arg(input, "pattern")?.to_string();
+                let base = input.get("path").and_then(Value::as_str).unwrap_or(".");
+                let path = self.resolve(base);
+                let case_insensitive = bool_arg(input, "case_insensitive");
+                self.check_readable(base)?;
+                let matches = task::spawn_blocking(move || tools::grep(&pattern, &path, case_insensitive)).await??;
+                let keep = self.confine(base);
+                let matches: Vec<GrepMatch> = matches
+                    .into_iter()
+                    .filter_map(|m| Some(GrepMatch { file: keep(&m.file)?, ..m }))
+                    .filter(|m| !self.read_denied(&m.file))
+                    .collect();
                 let output = matches.iter()
                     .take(10)
                     .map(|m| format!("{}:{} {}", m.file, m.line_num, m.content))
@@ -417,7 +1466,122 @@ TOOL_CALLS:
                     .join("\n");
                 Ok(format!("Found {} matches:\n{}", matches.len(), output))
             }
+            "webfetch" => {
+                let url = str_arg(input, "url")?;
+                let options = tools::FetchOptions {
+                    offset: usize_arg(input, "offset").unwrap_or(0),
+                    ..Default::default()
+                };
+                let page = tools::webfetch(url, &options, Some(&self.http_cache), &self.egress).await?;
+                let mut output = format!(
+                    "{} ({} {})\n",
+                    page.title.as_deref().unwrap_or(&page.url),
+                    page.status,
+                    page.content_type
+                );
+                output.push_str(&page.content);
+                if let Some(next) = page.next_offset {
+                    output.push_str(&format!(
+                        "\n\n[Showing {} of {} characters. Fetch again with offset {} for more.]",
+                        next - options.offset, page.total_chars, next
+                    ));
+                }
+                if page.truncated {
+                    output.push_str("\n\n[Download truncated at size limit.]");
+                }
+                Ok(output)
+            }
+            "websearch" => {
+                let query = str_arg(input, "query")?;
+                let results = tools::websearch(query, Some(&self.http_cache), &self.egress).await?;
+                Ok(results.iter()
+                    .map(|r| format!("{}\n{}\n{}", r.title, r.url, r.snippet))
+                    .collect::<Vec<_>>()
+                    .join("\n\n"))
+            }
             _ => Err(anyhow::anyhow!("Unknown tool: {}", call.tool)),
         }
     }
 }
+
+/// Runs `future` and measures how long it took.
+async fn timed<T>(future: impl Future<Output = T>) -> (T, Duration) {
+    let started = Instant::now();
+    let output = future.await;
+    (output, started.elapsed())
+}
+
+/// `value` with every string in it redacted.
+fn redact_value(redactor: &Redactor, value: &Value) -> Value {
+    match value {
+        Value::String(s) => Value::String(redactor.redact(s).into_owned()),
+        Value::Array(items) => Value::Array(items.iter().map(|v| redact_value(redactor, v)).collect()),
+        Value::Object(map) => Value::Object(map.iter().map(|(k, v)| (k.clone(), redact_value(redactor, v))).collect()),
+        other => other.clone(),
+    }
+}
+
+fn str_arg<'a>(input: &'a Value, key: &str) -> Result<&'a str> {
+    input.get(key)
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow::anyhow!("Missing required argument: {}", key))
+}
+
+fn usize_arg(input: &Value, key: &str) -> Option<usize> {
+    input.get(key).and_then(Value::as_u64).map(|v| v as usize)
+}
+
+fn bool_arg(input: &Value, key: &str) -> bool {
+    input.get(key).and_then(Value::as_bool).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thinking_is_only_sent_to_models_that_support_it() {
+        let agent = Agent::new("key".to_string()).with_max_tokens(1000);
+        let request = agent.request().unwrap();
+        assert_eq!(request["thinking"]["budget_tokens"], 4096);
+        assert_eq!(request["max_tokens"], 1000 + 4096);
+
+        for model in ["claude-3-5-haiku-20241022", "claude-3-haiku-20240307", "claude-3-5-sonnet-latest"] {
+            let request = Agent::new("key".to_string())
+                .with_model(model)
+                .with_max_tokens(1000)
+                .request()
+                .unwrap();
+            assert!(request.get("thinking").is_none(), "{}", model);
+            assert_eq!(request["max_tokens"], 1000, "{}", model);
+        }
+
+        assert!(supports_thinking("claude-3-7-sonnet-20250219"));
+        assert!(supports_thinking("claude-haiku-4-5"));
+        assert!(supports_thinking(DEFAULT_MODEL));
+    }
+
+    #[test]
+    fn retry_delay_grows_up_to_the_cap_with_jitter() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        };
+        for (attempt, ceiling) in [(1, 100), (2, 200), (3, 400), (4, 800), (5, 1000), (40, 1000)] {
+            let ceiling = Duration::from_millis(ceiling);
+            for _ in 0..50 {
+                let delay = policy.delay(attempt, None);
+                assert!(delay >= ceiling / 2 && delay <= ceiling, "attempt {}: {:?}", attempt, delay);
+            }
+        }
+    }
+
+    #[test]
+    fn retry_after_wins_but_is_capped() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(7))), Duration::from_secs(7));
+        assert_eq!(policy.delay(3, Some(Duration::ZERO)), Duration::ZERO);
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(3600))), policy.max_delay);
+    }
+}
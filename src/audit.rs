@@ -0,0 +1,284 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// The `[audit]` config table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditSettings {
+    /// Record every tool call.
+    pub enabled: bool,
+    /// Where the log is written. Defaults to `AuditLog::default_path()`.
+    pub path: Option<PathBuf>,
+}
+
+impl Default for AuditSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: None,
+        }
+    }
+}
+
+/// How a tool call got past, or was stopped by, the permission checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditDecision {
+    /// Ran without asking.
+    Allowed,
+    /// Ran after the user or approver said yes.
+    Approved,
+    /// Refused by the permission mode, workspace or redaction settings.
+    Denied,
+    /// The user or approver said no.
+    Rejected,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditStatus {
+    Ok,
+    Error,
+    /// Cancelled with Ctrl-C before it finished.
+    Interrupted,
+}
+
+/// One line of the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Unix time, in seconds, when the call started.
+    pub timestamp: u64,
+    pub session_id: String,
+    pub tool_use_id: String,
+    pub tool: String,
+    /// The call's arguments, redacted.
+    pub input: Value,
+    pub decision: AuditDecision,
+    pub status: AuditStatus,
+    /// For `bash`, the command's exit code; unset if it was killed by a
+    /// signal or never ran.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    /// SHA-256 of the tool result as the model received it, after
+    /// redaction, so it can be checked against the saved session. Unset
+    /// for interrupted calls.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_sha256: Option<String>,
+    /// The workspace root the call ran in.
+    pub workspace: String,
+}
+
+impl AuditEntry {
+    pub fn output_hash(output: &str) -> String {
+        format!("{:x}", Sha256::digest(output.as_bytes()))
+    }
+}
+
+/// An append-only JSONL file with one `AuditEntry` per tool call. Entries
+/// are only ever appended, one `write` per line, so several Forge processes
+/// can share a log.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// `$XDG_DATA_HOME/forge/audit.jsonl`, or
+    /// `~/.local/share/forge/audit.jsonl`.
+    pub fn default_path() -> PathBuf {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+            .unwrap_or_else(std::env::temp_dir)
+            .join("forge")
+            .join("audit.jsonl")
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, entry: &AuditEntry) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut options = OpenOptions::new();
+        options.append(true).create(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        options.open(&self.path)?.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// The entries matching `query`, oldest first, and the number of lines
+    /// that couldn't be parsed (e.g. one cut short by a crash).
+    pub fn read(&self, query: &AuditQuery) -> Result<(Vec<AuditEntry>, usize)> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+            Err(e) => return Err(e.into()),
+        };
+        let mut entries = Vec::new();
+        let mut skipped = 0;
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<AuditEntry>(&line) {
+                Ok(entry) if query.matches(&entry) => entries.push(entry),
+                Ok(_) => {}
+                Err(_) => skipped += 1,
+            }
+        }
+        Ok((entries, skipped))
+    }
+}
+
+/// Filters for `AuditLog::read`. Unset fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    /// A session ID, or the start of one.
+    pub session: Option<String>,
+    pub tool: Option<String>,
+    /// Unix time, inclusive.
+    pub since: Option<u64>,
+    /// Unix time, exclusive.
+    pub until: Option<u64>,
+}
+
+impl AuditQuery {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.session.as_ref().is_none_or(|s| entry.session_id.starts_with(s.as_str()))
+            && self.tool.as_ref().is_none_or(|t| &entry.tool == t)
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.until.is_none_or(|until| entry.timestamp < until)
+    }
+}
+
+/// Parses a point in time for `--since` and `--until`: Unix seconds, a
+/// duration ago like `30m`, `12h`, `7d` or `2w`, or a UTC date like
+/// `2025-06-01` or `2025-06-01T14:30`.
+pub fn parse_time(text: &str, now: u64) -> Result<u64> {
+    let text = text.trim();
+    let invalid = || anyhow::anyhow!("Invalid time '{}': use Unix seconds, 30m, 12h, 7d, 2w or 2025-06-01[T14:30[:00]]", text);
+
+    if let Ok(secs) = text.parse::<u64>() {
+        return Ok(secs);
+    }
+    let units = [('s', 1), ('m', 60), ('h', 3600), ('d', 86400), ('w', 7 * 86400)];
+    for (suffix, secs) in units {
+        if let Some(n) = text.strip_suffix(suffix).and_then(|n| n.parse::<u64>().ok()) {
+            let ago = n.checked_mul(secs).ok_or_else(invalid)?;
+            return Ok(now.saturating_sub(ago));
+        }
+    }
+
+    let (date, time) = text.split_once(['T', ' ']).unwrap_or((text, "00:00"));
+    let date: Vec<i64> = date.split('-').map(str::parse).collect::<Result<_, _>>().map_err(|_| invalid())?;
+    let time: Vec<u64> = time
+        .trim_end_matches('Z')
+        .split(':')
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid())?;
+    let ([year, month, day], [hour, minute, second @ ..]) = (date.as_slice(), time.as_slice()) else {
+        return Err(invalid());
+    };
+    let second = match second {
+        [] => 0,
+        [s] => *s,
+        _ => return Err(invalid()),
+    };
+    if !(1970..=9999).contains(year)
+        || !(1..=12).contains(month)
+        || !(1..=31).contains(day)
+        || *hour > 23
+        || *minute > 59
+        || second > 60
+    {
+        return Err(invalid());
+    }
+    let days = days_from_civil(*year, *month, *day);
+    u64::try_from(days)
+        .map(|days| days * 86400 + hour * 3600 + minute * 60 + second)
+        .map_err(|_| invalid())
+}
+
+/// `secs` as `YYYY-MM-DD HH:MM:SS` in UTC.
+pub fn format_time(secs: u64) -> String {
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let rem = secs % 86400;
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, rem / 3600, rem % 3600 / 60, rem % 60)
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_750_000_000;
+
+    #[test]
+    fn parses_times() {
+        assert_eq!(parse_time("1700000000", NOW).unwrap(), 1_700_000_000);
+        assert_eq!(parse_time("30m", NOW).unwrap(), NOW - 1800);
+        assert_eq!(parse_time("7d", NOW).unwrap(), NOW - 7 * 86400);
+        assert_eq!(parse_time("2w", NOW).unwrap(), NOW - 14 * 86400);
+        assert_eq!(parse_time("99999999d", NOW).unwrap(), 0);
+        assert_eq!(parse_time("2025-06-01", NOW).unwrap(), 1_748_736_000);
+        assert_eq!(parse_time("2025-06-01T14:30", NOW).unwrap(), 1_748_788_200);
+        assert_eq!(format_time(1_748_788_200), "2025-06-01 14:30:00");
+    }
+
+    #[test]
+    fn rejects_overflowing_times() {
+        for text in ["999999999999999w", "18446744073709551615d", "99999999999999999h", "99999999999-01-01"] {
+            assert!(parse_time(text, NOW).is_err(), "{} should be rejected", text);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_times() {
+        for text in ["", "yesterday", "2025-13-01", "2025-06-01T25:00", "12x", "-5d"] {
+            assert!(parse_time(text, NOW).is_err(), "{} should be rejected", text);
+        }
+    }
+}
@@ -0,0 +1,522 @@
+use anyhow::Result;
+use reqwest::{Client, Url};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::agent::{Agent, AgentLimits, Approver, RetryPolicy, ThinkingSettings, ToolCall, DEFAULT_SYSTEM_PROMPT};
+use crate::audit::AuditLog;
+use crate::cache::HttpCache;
+use crate::egress::EgressPolicy;
+use crate::events::{AgentEvent, EventSink};
+use crate::hooks::{HookSettings, Hooks};
+use crate::instructions::Instructions;
+use crate::interrupt::Interrupt;
+use crate::output::Output;
+use crate::permission::PermissionMode;
+use crate::provider::Provider;
+use crate::redact::{RedactionPolicy, Redactor};
+use crate::sandbox::{Sandbox, SandboxPolicy};
+use crate::tools::{self, Tool};
+use crate::usage::{Budget, PriceTable};
+use crate::workspace::{OutsideAccess, Workspace};
+
+/// Configures an `Agent` for use as a library. Settings can be given in any
+/// order; `build` applies them consistently (a replaced system prompt still
+/// gets its appended parts, a removed tool stays removed even if it was
+/// registered) and checks them before anything runs.
+///
+/// Defaults suit embedding, so a few differ from the `forge` binary's,
+/// which sets them from its config: no audit log, no `FORGE.md` files, and
+/// no Ctrl-C handling.
+///
+/// ```no_run
+/// # async fn example() -> anyhow::Result<()> {
+/// use forge::{Agent, NullSink};
+/// use forge::permission::PermissionMode;
+///
+/// let mut agent = Agent::builder(std::env::var("ANTHROPIC_API_KEY")?)
+///     .model("claude-sonnet-4-5")
+///     .append_system_prompt("Answer in one paragraph.")
+///     .remove_tool("websearch")
+///     .cwd("/srv/checkout")
+///     .permission_mode(PermissionMode::ReadOnly)
+///     .max_turns(10)
+///     .event_sink(NullSink)
+///     .build()?;
+/// let result = agent.run("What does this project do?").await?;
+/// println!("{}", result.text);
+/// # Ok(())
+/// # }
+/// ```
+pub struct AgentBuilder {
+    api_key: String,
+    provider: Provider,
+    base_url: Option<String>,
+    client: Option<Client>,
+    model: Option<String>,
+    max_tokens: Option<u32>,
+    prompt_caching: Option<bool>,
+    system_prompt: Option<String>,
+    appended_prompts: Vec<String>,
+    instructions: bool,
+    tools: Vec<Arc<dyn Tool>>,
+    removed_tools: Vec<String>,
+    cwd: Option<PathBuf>,
+    extra_dirs: Vec<PathBuf>,
+    outside_access: OutsideAccess,
+    sandbox: SandboxPolicy,
+    redaction: RedactionPolicy,
+    audit_log: Option<PathBuf>,
+    hooks: HookSettings,
+    permission_mode: PermissionMode,
+    approver: Option<Approver>,
+    limits: AgentLimits,
+    retry: Option<RetryPolicy>,
+    thinking: Option<ThinkingSettings>,
+    prices: Option<PriceTable>,
+    budget: Option<Budget>,
+    interrupt: Option<Interrupt>,
+    http_cache: Option<HttpCache>,
+    egress: Option<EgressPolicy>,
+    ask_fallback: Option<(bool, Option<String>)>,
+    events: Option<Arc<dyn EventSink>>,
+}
+
+impl AgentBuilder {
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            provider: Provider::default(),
+            base_url: None,
+            client: None,
+            model: None,
+            max_tokens: None,
+            prompt_caching: None,
+            system_prompt: None,
+            appended_prompts: Vec::new(),
+            instructions: false,
+            tools: Vec::new(),
+            removed_tools: Vec::new(),
+            cwd: None,
+            extra_dirs: Vec::new(),
+            outside_access: OutsideAccess::default(),
+            sandbox: SandboxPolicy::default(),
+            redaction: RedactionPolicy::default(),
+            audit_log: None,
+            hooks: HookSettings::default(),
+            permission_mode: PermissionMode::default(),
+            approver: None,
+            limits: AgentLimits::default(),
+            retry: None,
+            thinking: None,
+            prices: None,
+            budget: None,
+            interrupt: None,
+            http_cache: None,
+            egress: None,
+            ask_fallback: None,
+            events: None,
+        }
+    }
+
+    pub fn provider(mut self, provider: Provider) -> Self {
+        self.provider = provider;
+        self
+    }
+
+    /// Overrides the provider's base URL, e.g. for a proxy or a test server.
+    pub fn base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = Some(url.into());
+        self
+    }
+
+    /// HTTP client for API calls, e.g. one with custom timeouts or proxies.
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Output tokens for the visible answer; any thinking budget is added
+    /// on top.
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Turns prompt caching on or off, whatever the provider's default.
+    pub fn prompt_caching(mut self, enabled: bool) -> Self {
+        self.prompt_caching = Some(enabled);
+        self
+    }
+
+    /// Replaces the built-in system prompt.
+    pub fn system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.system_prompt = Some(prompt.into());
+        self
+    }
+
+    /// Adds to the end of the system prompt, built-in or replaced. Can be
+    /// called more than once.
+    pub fn append_system_prompt(mut self, extra: impl Into<String>) -> Self {
+        self.appended_prompts.push(extra.into());
+        self
+    }
+
+    /// Adds `FORGE.md` files from the user config directory, the working
+    /// directory and its parents to the end of the system prompt. Off by
+    /// default.
+    pub fn instructions(mut self, load: bool) -> Self {
+        self.instructions = load;
+        self
+    }
+
+    /// Offers an extra tool to the model. One with the same name as a
+    /// built-in tool replaces it.
+    pub fn tool(mut self, tool: impl Tool + 'static) -> Self {
+        self.tools.push(Arc::new(tool));
+        self
+    }
+
+    pub fn remove_tool(mut self, name: impl Into<String>) -> Self {
+        self.removed_tools.push(name.into());
+        self
+    }
+
+    /// The workspace root: tools run here, resolve relative paths against
+    /// it, and file tools stay inside it. Defaults to the process's working
+    /// directory.
+    pub fn cwd(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(dir.into());
+        self
+    }
+
+    /// Lets file tools use another directory besides the root. Can be
+    /// called more than once.
+    pub fn add_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.extra_dirs.push(dir.into());
+        self
+    }
+
+    /// Whether file tool calls outside the workspace are refused or go to
+    /// the approver. They go to the approver by default.
+    pub fn outside_access(mut self, access: OutsideAccess) -> Self {
+        self.outside_access = access;
+        self
+    }
+
+    /// Sandboxes `bash` commands if `policy.enabled` is set. Protections
+    /// this machine can't provide are reported as warnings when the agent
+    /// is built.
+    pub fn sandbox(mut self, policy: SandboxPolicy) -> Self {
+        self.sandbox = policy;
+        self
+    }
+
+    /// What is redacted from tool results and saved sessions, and which
+    /// files the file tools refuse to read. Secrets are redacted by default.
+    pub fn redaction(mut self, policy: RedactionPolicy) -> Self {
+        self.redaction = policy;
+        self
+    }
+
+    /// Appends a JSONL record of every tool call to `path`. Off by default.
+    pub fn audit_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.audit_log = Some(path.into());
+        self
+    }
+
+    /// Commands to run around tool calls, on each prompt and when the
+    /// model stops. None by default.
+    pub fn hooks(mut self, settings: HookSettings) -> Self {
+        self.hooks = settings;
+        self
+    }
+
+    pub fn permission_mode(mut self, mode: PermissionMode) -> Self {
+        self.permission_mode = mode;
+        self
+    }
+
+    /// Decides calls that the permission mode says need approval. Without
+    /// one they are asked about on the terminal, or refused if there is none.
+    pub fn approver(mut self, approver: impl Fn(&ToolCall) -> bool + Send + Sync + 'static) -> Self {
+        self.approver = Some(Arc::new(approver));
+        self
+    }
+
+    pub fn max_turns(mut self, max_turns: u32) -> Self {
+        self.limits.max_turns = max_turns;
+        self
+    }
+
+    pub fn limits(mut self, limits: AgentLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// How rate limits, overloads and server errors are retried.
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Extended thinking, on by default for models that support it.
+    pub fn thinking(mut self, thinking: ThinkingSettings) -> Self {
+        self.thinking = Some(thinking);
+        self
+    }
+
+    /// Model prices for cost reporting and spending limits, in place of the
+    /// built-in table.
+    pub fn price_table(mut self, prices: PriceTable) -> Self {
+        self.prices = Some(prices);
+        self
+    }
+
+    /// Token and spending limits. None by default.
+    pub fn budget(mut self, budget: Budget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Lets Ctrl-C, or whoever holds a clone, cancel the turn in flight.
+    pub fn interrupt(mut self, interrupt: Interrupt) -> Self {
+        self.interrupt = Some(interrupt);
+        self
+    }
+
+    /// The on-disk cache for `webfetch` and `websearch`.
+    pub fn http_cache(mut self, cache: HttpCache) -> Self {
+        self.http_cache = Some(cache);
+        self
+    }
+
+    /// Which hosts the web tools may reach.
+    pub fn egress_policy(mut self, policy: EgressPolicy) -> Self {
+        self.egress = Some(policy);
+        self
+    }
+
+    /// Whether `ask` may prompt on the terminal, and the answer it gives
+    /// when it can't.
+    pub fn ask_fallback(mut self, interactive: bool, default_answer: Option<String>) -> Self {
+        self.ask_fallback = Some((interactive, default_answer));
+        self
+    }
+
+    /// Where agent events go. Without one they are printed to the terminal.
+    pub fn event_sink(mut self, sink: impl EventSink + 'static) -> Self {
+        self.events = Some(Arc::new(sink));
+        self
+    }
+
+    pub fn build(self) -> Result<Agent> {
+        if self.api_key.trim().is_empty() {
+            return Err(anyhow::anyhow!("API key must not be empty"));
+        }
+        if self.model.as_deref().is_some_and(|m| m.trim().is_empty()) {
+            return Err(anyhow::anyhow!("Model name must not be empty"));
+        }
+        if self.limits.max_turns == 0 || self.limits.max_tool_calls == 0 || self.limits.max_parallel_tools == 0 {
+            return Err(anyhow::anyhow!("max_turns, max_tool_calls and max_parallel_tools must be at least 1"));
+        }
+        if self.max_tokens == Some(0) {
+            return Err(anyhow::anyhow!("max_tokens must be at least 1"));
+        }
+        if self.retry.is_some_and(|r| r.base_delay > r.max_delay) {
+            return Err(anyhow::anyhow!("The retry base delay must not exceed the max delay"));
+        }
+        if self.thinking.as_ref().is_some_and(|t| t.budget_tokens < 1024) {
+            return Err(anyhow::anyhow!("The thinking budget must be at least 1024 tokens"));
+        }
+
+        let mut provider = self.provider;
+        if let Some(url) = self.base_url {
+            provider.base_url = url;
+        }
+        if let Some(enabled) = self.prompt_caching {
+            provider.prompt_caching = Some(enabled);
+        }
+        match Url::parse(&provider.base_url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+            _ => return Err(anyhow::anyhow!("Invalid base URL: {}", provider.base_url)),
+        }
+
+        let known: Vec<String> = tools::definitions()
+            .into_iter()
+            .map(|d| d.name)
+            .chain(self.tools.iter().map(|t| t.definition().name))
+            .collect();
+        if let Some(name) = self.removed_tools.iter().find(|name| !known.contains(name)) {
+            return Err(anyhow::anyhow!("Cannot remove unknown tool: {}", name));
+        }
+
+        let mut workspace = match &self.cwd {
+            Some(dir) => Workspace::new(dir)
+                .ok()
+                .filter(|w| w.root().is_dir())
+                .ok_or_else(|| anyhow::anyhow!("Working directory not found: {}", dir.display()))?,
+            None => Workspace::current()
+                .map_err(|e| anyhow::anyhow!("Cannot resolve the working directory: {}", e))?,
+        };
+        for dir in &self.extra_dirs {
+            workspace = workspace
+                .with_dir(dir)
+                .map_err(|e| anyhow::anyhow!("Cannot add directory {}: {}", dir.display(), e))?;
+        }
+
+        let sandbox = self
+            .sandbox
+            .enabled
+            .then(|| Sandbox::new(&self.sandbox, &workspace))
+            .transpose()?;
+        let redactor =
+            Redactor::new(&self.redaction).map_err(|e| anyhow::anyhow!("Invalid redaction policy: {}", e))?;
+        let hooks = Hooks::new(&self.hooks).map_err(|e| anyhow::anyhow!("Invalid hooks: {}", e))?;
+        let instructions = if self.instructions {
+            Instructions::load(&workspace, &redactor)
+        } else {
+            Instructions::default()
+        };
+
+        let mut agent = Agent::new(self.api_key)
+            .with_provider(provider)
+            .with_limits(self.limits)
+            .with_permission_mode(self.permission_mode)
+            .with_workspace(workspace)
+            .with_outside_access(self.outside_access)
+            .with_redactor(redactor)
+            .with_hooks(hooks)
+            .with_system_prompt(self.system_prompt.unwrap_or_else(|| DEFAULT_SYSTEM_PROMPT.to_string()));
+        for extra in &self.appended_prompts {
+            agent = agent.append_system_prompt(extra);
+        }
+        agent = agent.with_instructions(&instructions);
+        for tool in self.tools {
+            agent = agent.with_tool(tool);
+        }
+        agent = agent.with_disabled_tools(&self.removed_tools);
+        if let Some(model) = self.model {
+            agent = agent.with_model(model);
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            agent = agent.with_max_tokens(max_tokens);
+        }
+        if let Some(retry) = self.retry {
+            agent = agent.with_retry_policy(retry);
+        }
+        if let Some(thinking) = self.thinking {
+            agent = agent.with_thinking(thinking);
+        }
+        if let Some(prices) = self.prices {
+            agent = agent.with_price_table(prices);
+        }
+        if let Some(budget) = self.budget {
+            agent = agent.with_budget(budget);
+        }
+        if let Some(interrupt) = self.interrupt {
+            agent = agent.with_interrupt(interrupt);
+        }
+        if let Some(cache) = self.http_cache {
+            agent = agent.with_http_cache(cache);
+        }
+        if let Some(policy) = self.egress {
+            agent = agent.with_egress_policy(policy);
+        }
+        if let Some((interactive, default_answer)) = self.ask_fallback {
+            agent = agent.with_ask_fallback(interactive, default_answer);
+        }
+        if let Some(client) = self.client {
+            agent = agent.with_client(client);
+        }
+        if let Some(path) = self.audit_log {
+            agent = agent.with_audit_log(AuditLog::new(path));
+        }
+        if let Some(approver) = self.approver {
+            agent = agent.with_approver(move |call| approver(call));
+        }
+        if !instructions.warnings.is_empty() {
+            let events = self.events.clone().unwrap_or_else(|| Arc::new(Output::new()));
+            for warning in &instructions.warnings {
+                events.emit(&AgentEvent::Warning {
+                    message: warning.clone(),
+                });
+            }
+        }
+        if let Some(sandbox) = sandbox {
+            let events = self.events.clone().unwrap_or_else(|| Arc::new(Output::new()));
+            for gap in sandbox.gaps() {
+                events.emit(&AgentEvent::Warning {
+                    message: format!("Sandbox: {}", gap),
+                });
+            }
+            agent = agent.with_sandbox(sandbox);
+        }
+        if let Some(events) = self.events {
+            agent = agent.with_event_sink(events);
+        }
+        Ok(agent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NullSink;
+
+    fn builder() -> AgentBuilder {
+        AgentBuilder::new("key").event_sink(NullSink)
+    }
+
+    fn error(builder: AgentBuilder) -> String {
+        match builder.build() {
+            Ok(_) => panic!("expected build to fail"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn defaults_build() {
+        let agent = builder().build().unwrap();
+        assert_eq!(agent.workspace().root(), std::env::current_dir().unwrap().canonicalize().unwrap());
+    }
+
+    #[test]
+    fn a_missing_key_or_model_is_an_error() {
+        assert_eq!(error(AgentBuilder::new("")), "API key must not be empty");
+        assert_eq!(error(AgentBuilder::new("  ")), "API key must not be empty");
+        assert_eq!(error(builder().model("")), "Model name must not be empty");
+    }
+
+    #[test]
+    fn out_of_range_settings_are_errors() {
+        assert_eq!(error(builder().max_tokens(0)), "max_tokens must be at least 1");
+        assert!(error(builder().max_turns(0)).contains("must be at least 1"));
+        let retry = RetryPolicy {
+            base_delay: std::time::Duration::from_secs(10),
+            max_delay: std::time::Duration::from_secs(1),
+            ..RetryPolicy::default()
+        };
+        assert_eq!(error(builder().retry_policy(retry)), "The retry base delay must not exceed the max delay");
+        let thinking = ThinkingSettings {
+            budget_tokens: 512,
+            ..ThinkingSettings::default()
+        };
+        assert_eq!(error(builder().thinking(thinking)), "The thinking budget must be at least 1024 tokens");
+    }
+
+    #[test]
+    fn bad_urls_tools_and_directories_are_errors() {
+        assert_eq!(error(builder().base_url("ftp://example.com")), "Invalid base URL: ftp://example.com");
+        assert_eq!(error(builder().base_url("not a url")), "Invalid base URL: not a url");
+        assert_eq!(error(builder().remove_tool("teleport")), "Cannot remove unknown tool: teleport");
+        assert!(builder().remove_tool("websearch").build().is_ok());
+        assert!(error(builder().cwd("/nonexistent/forge-test")).starts_with("Working directory not found"));
+        assert!(error(builder().add_dir("/nonexistent/forge-test")).starts_with("Cannot add directory"));
+    }
+}
@@ -0,0 +1,332 @@
+use anyhow::Result;
+use reqwest::header::{HeaderMap, HeaderValue, CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::types::{NetworkError, ToolError};
+
+/// Metadata and body of a cached HTTP response. The body is stored next to
+/// the metadata file so large pages don't bloat the JSON.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub url: String,
+    pub status: u16,
+    pub content_type: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Unix seconds when the response was stored or last revalidated.
+    pub stored_at: u64,
+    /// `max-age` from `Cache-Control`, if the server sent one.
+    pub max_age: Option<u64>,
+    /// `no-cache`: the entry must be revalidated before every use.
+    pub no_cache: bool,
+    /// The body was cut off at the caller's download limit.
+    pub truncated: bool,
+    #[serde(skip)]
+    pub body: Vec<u8>,
+}
+
+pub enum Lookup {
+    /// Usable without contacting the server.
+    Fresh(CachedResponse),
+    /// Expired; revalidate with `conditional_headers` before using.
+    Stale(CachedResponse),
+    Miss,
+}
+
+/// Content-addressed on-disk cache for web tool responses, keyed by the
+/// SHA-256 of the URL or search query.
+pub struct HttpCache {
+    dir: PathBuf,
+    ttl: Duration,
+    offline: bool,
+}
+
+impl HttpCache {
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl,
+            offline: false,
+        }
+    }
+
+    /// Serve from cache only; misses fail instead of hitting the network.
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// `.forge/cache/http` when run inside a project that has a `.forge`
+    /// directory, otherwise the user cache dir.
+    pub fn default_dir() -> PathBuf {
+        if Path::new(".forge").is_dir() {
+            return PathBuf::from(".forge/cache/http");
+        }
+        let base = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .unwrap_or_else(std::env::temp_dir);
+        base.join("forge").join("http")
+    }
+
+    pub fn lookup(&self, key: &str) -> Result<Lookup> {
+        let entry = match self.load(key) {
+            Some(entry) => entry,
+            None if self.offline => {
+                return Err(ToolError::from(NetworkError::CacheMiss(key.to_string())).into())
+            }
+            None => return Ok(Lookup::Miss),
+        };
+
+        if self.offline || self.is_fresh(&entry) {
+            Ok(Lookup::Fresh(entry))
+        } else {
+            Ok(Lookup::Stale(entry))
+        }
+    }
+
+    /// Stores a response unless the server asked for `no-store`.
+    pub fn store(&self, key: &str, mut entry: CachedResponse, headers: &HeaderMap) -> Result<CachedResponse> {
+        let directives = header_str(headers, CACHE_CONTROL).unwrap_or_default().to_lowercase();
+        entry.etag = header_str(headers, ETAG);
+        entry.last_modified = header_str(headers, LAST_MODIFIED);
+        entry.max_age = parse_max_age(&directives);
+        entry.no_cache = directives.contains("no-cache");
+        entry.stored_at = now();
+
+        if directives.contains("no-store") {
+            return Ok(entry);
+        }
+
+        fs::create_dir_all(&self.dir)?;
+        let (meta_path, body_path) = self.paths(key);
+        write_atomic(&body_path, &entry.body)?;
+        write_atomic(&meta_path, serde_json::to_string(&entry)?.as_bytes())?;
+        Ok(entry)
+    }
+
+    /// Marks a stale entry fresh again after a `304 Not Modified`.
+    pub fn refresh(&self, key: &str, mut entry: CachedResponse, headers: &HeaderMap) -> Result<CachedResponse> {
+        if let Some(directives) = header_str(headers, CACHE_CONTROL) {
+            entry.max_age = parse_max_age(&directives.to_lowercase());
+        }
+        entry.stored_at = now();
+        let (meta_path, _) = self.paths(key);
+        write_atomic(&meta_path, serde_json::to_string(&entry)?.as_bytes())?;
+        Ok(entry)
+    }
+
+    /// `If-None-Match` / `If-Modified-Since` headers for revalidating `entry`.
+    pub fn conditional_headers(entry: &CachedResponse) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(value) = entry.etag.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+            headers.insert(IF_NONE_MATCH, value);
+        }
+        if let Some(value) = entry.last_modified.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+            headers.insert(IF_MODIFIED_SINCE, value);
+        }
+        headers
+    }
+
+    fn is_fresh(&self, entry: &CachedResponse) -> bool {
+        if entry.no_cache {
+            return false;
+        }
+        let lifetime = entry.max_age.unwrap_or(self.ttl.as_secs());
+        now().saturating_sub(entry.stored_at) < lifetime
+    }
+
+    fn load(&self, key: &str) -> Option<CachedResponse> {
+        let (meta_path, body_path) = self.paths(key);
+        let mut entry: CachedResponse = serde_json::from_str(&fs::read_to_string(meta_path).ok()?).ok()?;
+        entry.body = fs::read(body_path).ok()?;
+        Some(entry)
+    }
+
+    fn paths(&self, key: &str) -> (PathBuf, PathBuf) {
+        let hash = format!("{:x}", Sha256::digest(key.as_bytes()));
+        (
+            self.dir.join(format!("{}.json", hash)),
+            self.dir.join(format!("{}.body", hash)),
+        )
+    }
+}
+
+impl Default for HttpCache {
+    fn default() -> Self {
+        Self::new(Self::default_dir(), Duration::from_secs(60 * 60))
+    }
+}
+
+fn header_str(headers: &HeaderMap, name: reqwest::header::HeaderName) -> Option<String> {
+    headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string)
+}
+
+fn parse_max_age(directives: &str) -> Option<u64> {
+    directives
+        .split(',')
+        .filter_map(|d| d.trim().strip_prefix("max-age="))
+        .find_map(|v| v.trim_matches('"').parse().ok())
+}
+
+/// Writes through a temp file named after the whole target, this process
+/// and a counter, so the `.meta` and `.body` of one entry, or concurrent
+/// fetches of one URL, never share a temp file.
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".tmp.{}.{}", std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed)));
+    let tmp = path.with_file_name(name);
+    let written = fs::write(&tmp, contents).and_then(|()| fs::rename(&tmp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    Ok(written?)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    fn cache(ttl: Duration) -> HttpCache {
+        let dir = std::env::temp_dir().join(format!(
+            "forge-cache-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        HttpCache::new(dir, ttl)
+    }
+
+    fn response(body: &str) -> CachedResponse {
+        CachedResponse {
+            url: "https://example.com/".to_string(),
+            status: 200,
+            content_type: "text/html".to_string(),
+            etag: None,
+            last_modified: None,
+            stored_at: 0,
+            max_age: None,
+            no_cache: false,
+            truncated: false,
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn entries_are_fresh_within_the_ttl() {
+        let cache = cache(Duration::from_secs(3600));
+        cache.store("k", response("hello"), &HeaderMap::new()).unwrap();
+        match cache.lookup("k").unwrap() {
+            Lookup::Fresh(entry) => assert_eq!(entry.body, b"hello"),
+            _ => panic!("expected a fresh entry"),
+        }
+        assert!(matches!(cache.lookup("other").unwrap(), Lookup::Miss));
+    }
+
+    #[test]
+    fn entries_go_stale_after_the_ttl() {
+        let cache = cache(Duration::ZERO);
+        cache.store("k", response("hello"), &HeaderMap::new()).unwrap();
+        assert!(matches!(cache.lookup("k").unwrap(), Lookup::Stale(_)));
+    }
+
+    #[test]
+    fn max_age_overrides_the_ttl() {
+        let cache = cache(Duration::ZERO);
+        cache.store("long", response("a"), &headers(&[("cache-control", "public, max-age=3600")])).unwrap();
+        assert!(matches!(cache.lookup("long").unwrap(), Lookup::Fresh(_)));
+
+        let cache = HttpCache::new(cache.dir.clone(), Duration::from_secs(3600));
+        cache.store("short", response("b"), &headers(&[("cache-control", "max-age=0")])).unwrap();
+        assert!(matches!(cache.lookup("short").unwrap(), Lookup::Stale(_)));
+    }
+
+    #[test]
+    fn no_cache_entries_are_always_revalidated() {
+        let cache = cache(Duration::from_secs(3600));
+        let stored = cache
+            .store("k", response("a"), &headers(&[("cache-control", "no-cache"), ("etag", "\"v1\"")]))
+            .unwrap();
+        assert!(stored.no_cache);
+        match cache.lookup("k").unwrap() {
+            Lookup::Stale(entry) => {
+                let conditional = HttpCache::conditional_headers(&entry);
+                assert_eq!(conditional.get(IF_NONE_MATCH).unwrap(), "\"v1\"");
+            }
+            _ => panic!("expected a stale entry"),
+        }
+    }
+
+    #[test]
+    fn no_store_responses_are_not_written() {
+        let cache = cache(Duration::from_secs(3600));
+        let entry = cache.store("k", response("secret"), &headers(&[("cache-control", "No-Store")])).unwrap();
+        assert_eq!(entry.body, b"secret");
+        assert!(matches!(cache.lookup("k").unwrap(), Lookup::Miss));
+        assert!(!cache.dir.exists());
+    }
+
+    #[test]
+    fn offline_misses_fail_and_stale_hits_are_served() {
+        let cache = cache(Duration::ZERO).offline(true);
+        let error = cache.lookup("https://example.com/missing").err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<ToolError>(),
+            Some(ToolError::NetworkError(NetworkError::CacheMiss(key))) if key == "https://example.com/missing"
+        ));
+
+        cache.store("k", response("old"), &HeaderMap::new()).unwrap();
+        assert!(matches!(cache.lookup("k").unwrap(), Lookup::Fresh(_)));
+    }
+
+    #[test]
+    fn concurrent_writes_leave_one_whole_file() {
+        let cache = cache(Duration::from_secs(3600));
+        fs::create_dir_all(&cache.dir).unwrap();
+        let path = cache.dir.join("shared");
+        let bodies: Vec<Vec<u8>> = (0..8u8).map(|i| vec![b'a' + i; 64 * 1024]).collect();
+
+        std::thread::scope(|scope| {
+            for body in &bodies {
+                let path = &path;
+                scope.spawn(move || {
+                    for _ in 0..20 {
+                        write_atomic(path, body).unwrap();
+                    }
+                });
+            }
+        });
+
+        let written = fs::read(&path).unwrap();
+        assert!(bodies.contains(&written));
+        let leftovers: Vec<_> = fs::read_dir(&cache.dir).unwrap().collect();
+        assert_eq!(leftovers.len(), 1, "temp files left behind: {:?}", leftovers);
+    }
+}
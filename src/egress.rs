@@ -0,0 +1,247 @@
+use anyhow::Result;
+use reqwest::header::{HeaderMap, LOCATION};
+use reqwest::{redirect, Url};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use crate::types::{NetworkError, ToolError};
+
+/// Network egress rules for the web tools, from the `[network]` config
+/// table.
+///
+/// Every hop, including redirects, is checked before connecting: the scheme
+/// must be allowed, the host must pass the deny/allow lists, and every
+/// address it resolves to must be public unless the host is listed in
+/// `allow_private_hosts`. Connections are pinned to the addresses that were
+/// checked so a second DNS lookup can't swap them out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EgressPolicy {
+    pub allowed_schemes: Vec<String>,
+    /// When non-empty, only these domains (and their subdomains) are reachable.
+    pub allow_domains: Vec<String>,
+    /// Domains (and their subdomains) that are never reachable.
+    pub deny_domains: Vec<String>,
+    /// Hosts that may resolve to loopback, link-local or private addresses.
+    pub allow_private_hosts: Vec<String>,
+    pub max_redirects: usize,
+}
+
+impl Default for EgressPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_schemes: vec!["http".to_string(), "https".to_string()],
+            allow_domains: Vec::new(),
+            deny_domains: Vec::new(),
+            allow_private_hosts: Vec::new(),
+            max_redirects: 10,
+        }
+    }
+}
+
+impl EgressPolicy {
+    /// Checks the scheme and host of `url` without resolving it.
+    pub fn check_url(&self, url: &str) -> Result<Url, NetworkError> {
+        let parsed = Url::parse(url).map_err(|_| NetworkError::InvalidUrl(url.to_string()))?;
+
+        if !self.allowed_schemes.iter().any(|s| s.eq_ignore_ascii_case(parsed.scheme())) {
+            return Err(NetworkError::SchemeNotAllowed(parsed.scheme().to_string()));
+        }
+
+        let host = host_of(&parsed)?;
+        if self.deny_domains.iter().any(|d| domain_matches(&host, d)) {
+            return Err(NetworkError::HostDenied(host));
+        }
+        if !self.allow_domains.is_empty() && !self.allow_domains.iter().any(|d| domain_matches(&host, d)) {
+            return Err(NetworkError::HostNotAllowed(host));
+        }
+
+        Ok(parsed)
+    }
+
+    /// Resolves the host of an already checked URL and rejects it if any
+    /// address is in a blocked range.
+    pub async fn resolve(&self, url: &Url) -> Result<Vec<SocketAddr>, NetworkError> {
+        let host = host_of(url)?;
+        let port = url.port_or_known_default().unwrap_or(443);
+
+        let addrs: Vec<SocketAddr> = match host.parse::<IpAddr>() {
+            Ok(ip) => vec![SocketAddr::new(ip, port)],
+            Err(_) => tokio::net::lookup_host((host.as_str(), port))
+                .await
+                .map_err(|_| NetworkError::Unresolvable(host.clone()))?
+                .collect(),
+        };
+        if addrs.is_empty() {
+            return Err(NetworkError::Unresolvable(host));
+        }
+
+        let private_ok = self.allow_private_hosts.iter().any(|h| h.eq_ignore_ascii_case(&host));
+        if !private_ok {
+            if let Some(addr) = addrs.iter().find(|a| is_blocked(a.ip())) {
+                return Err(NetworkError::BlockedAddress { host, addr: addr.ip() });
+            }
+        }
+
+        Ok(addrs)
+    }
+
+    /// Sends a GET request, re-validating every redirect hop against the
+    /// policy. Returns the final response.
+    pub async fn get(
+        &self,
+        url: &str,
+        user_agent: &str,
+        timeout: Duration,
+        headers: HeaderMap,
+    ) -> Result<reqwest::Response> {
+        let mut current = self.check_url(url).map_err(ToolError::from)?;
+
+        for _ in 0..=self.max_redirects {
+            let addrs = self.resolve(&current).await.map_err(ToolError::from)?;
+            let client = reqwest::Client::builder()
+                .user_agent(user_agent)
+                .timeout(timeout)
+                .redirect(redirect::Policy::none())
+                .resolve_to_addrs(&host_of(&current).map_err(ToolError::from)?, &addrs)
+                .build()?;
+
+            let response = client
+                .get(current.clone())
+                .headers(headers.clone())
+                .send()
+                .await
+                .map_err(|e| ToolError::from(NetworkError::from(e)))?;
+
+            if !response.status().is_redirection() {
+                return Ok(response);
+            }
+            let Some(location) = response.headers().get(LOCATION).and_then(|v| v.to_str().ok()) else {
+                return Ok(response);
+            };
+            let next = current
+                .join(location)
+                .map_err(|_| ToolError::from(NetworkError::InvalidUrl(location.to_string())))?;
+            current = self.check_url(next.as_str()).map_err(ToolError::from)?;
+        }
+
+        Err(ToolError::from(NetworkError::TooManyRedirects(self.max_redirects)).into())
+    }
+}
+
+fn host_of(url: &Url) -> Result<String, NetworkError> {
+    url.host_str()
+        .map(|h| h.trim_start_matches('[').trim_end_matches(']').to_lowercase())
+        .filter(|h| !h.is_empty())
+        .ok_or_else(|| NetworkError::InvalidUrl(url.to_string()))
+}
+
+/// `example.com` and `*.example.com` both match `example.com` and any
+/// subdomain of it.
+fn domain_matches(host: &str, pattern: &str) -> bool {
+    let pattern = pattern.trim_start_matches("*.").trim_end_matches('.').to_lowercase();
+    host == pattern || host.ends_with(&format!(".{}", pattern))
+}
+
+/// Loopback, link-local, private (RFC 1918 / ULA), carrier-grade NAT,
+/// unspecified, broadcast and multicast addresses, including IPv6
+/// addresses that carry a blocked IPv4 address.
+fn is_blocked(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => is_blocked_v4(v4),
+        IpAddr::V6(v6) => is_blocked_v6(v6) || embedded_v4(v6).is_some_and(is_blocked_v4),
+    }
+}
+
+/// The IPv4 address an IPv6 address routes to, for the forms that embed
+/// one: IPv4-mapped `::ffff:a.b.c.d`, IPv4-compatible `::a.b.c.d`, NAT64
+/// `64:ff9b::/96` and `64:ff9b:1::/48`, 6to4 `2002::/16` and Teredo
+/// `2001::/32`.
+fn embedded_v4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let s = ip.segments();
+    let v4 = |high: u16, low: u16| Ipv4Addr::from(((high as u32) << 16) | low as u32);
+    match s {
+        [0, 0, 0, 0, 0, 0xffff, ..] | [0, 0, 0, 0, 0, 0, ..] => Some(v4(s[6], s[7])),
+        [0x64, 0xff9b, 0, 0, 0, 0, ..] | [0x64, 0xff9b, 1, ..] => Some(v4(s[6], s[7])),
+        [0x2002, ..] => Some(v4(s[1], s[2])),
+        // The client address is stored inverted.
+        [0x2001, 0, ..] => Some(v4(!s[6], !s[7])),
+        _ => None,
+    }
+}
+
+fn is_blocked_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || (a == 100 && (64..128).contains(&b))
+        || a == 0
+}
+
+fn is_blocked_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocked(ip: &str) -> bool {
+        is_blocked(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn blocks_internal_v4() {
+        for ip in [
+            "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254",
+            "100.64.0.1", "0.0.0.0", "0.1.2.3", "255.255.255.255", "224.0.0.1",
+        ] {
+            assert!(blocked(ip), "{} should be blocked", ip);
+        }
+        for ip in ["8.8.8.8", "1.1.1.1", "100.128.0.1", "172.32.0.1", "93.184.216.34"] {
+            assert!(!blocked(ip), "{} should be allowed", ip);
+        }
+    }
+
+    #[test]
+    fn blocks_internal_v6() {
+        for ip in ["::1", "::", "fc00::1", "fd12:3456::1", "fe80::1", "ff02::1"] {
+            assert!(blocked(ip), "{} should be blocked", ip);
+        }
+        for ip in ["2606:4700:4700::1111", "2001:4860:4860::8888"] {
+            assert!(!blocked(ip), "{} should be allowed", ip);
+        }
+    }
+
+    #[test]
+    fn blocks_v6_embedding_internal_v4() {
+        for ip in [
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+            "::127.0.0.1",
+            "::192.168.0.1",
+            "64:ff9b::7f00:1",
+            "64:ff9b::169.254.169.254",
+            "64:ff9b:1::a00:1",
+            "2002:7f00:1::",
+            "2002:a9fe:a9fe::1",
+            // Teredo with client 127.0.0.1 (inverted: 80ff:fffe).
+            "2001:0:4136:e378:8000:63bf:80ff:fffe",
+        ] {
+            assert!(blocked(ip), "{} should be blocked", ip);
+        }
+        for ip in ["::ffff:8.8.8.8", "64:ff9b::808:808", "2002:808:808::1"] {
+            assert!(!blocked(ip), "{} should be allowed", ip);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::agent::StopReason;
+use crate::shell::Classification;
+use crate::tools::OutputStream;
+use crate::types::TodoItem;
+use crate::usage::Usage;
+
+/// Everything the agent reports while it works. `Agent` never prints; it
+/// hands these to its `EventSink`, and the terminal renderer (`Output`) is
+/// just one sink. Serialized, these are the `stream-json` lines.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AgentEvent {
+    Thinking {
+        text: String,
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        redacted: bool,
+    },
+    AssistantText {
+        text: String,
+    },
+    /// A tool call is about to run; `index` counts from 1 within the turn.
+    ToolStart {
+        id: String,
+        tool: String,
+        input: Value,
+        /// One-line description, e.g. `bash cargo test`.
+        summary: String,
+        /// How risky a `bash` command looks, for built-in `bash` calls.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        classification: Option<Classification>,
+        index: usize,
+        total: usize,
+    },
+    /// A line a running tool wrote, as it arrives.
+    ToolOutput {
+        id: String,
+        stream: OutputStream,
+        line: String,
+    },
+    ToolResult {
+        id: String,
+        tool: String,
+        content: String,
+        is_error: bool,
+    },
+    Todos {
+        todos: Vec<TodoItem>,
+    },
+    /// Tokens used by one API call.
+    Usage {
+        usage: Usage,
+        cost_usd: Option<f64>,
+    },
+    /// Something worth telling the user that didn't stop the run, such as
+    /// a retry or a budget nearing its limit.
+    Warning {
+        message: String,
+    },
+    Error {
+        message: String,
+    },
+    /// The request ended early. Not sent for `Completed`.
+    Stopped {
+        reason: StopReason,
+        message: String,
+    },
+    /// A spending limit ended the request, with what it got done first.
+    BudgetExceeded {
+        detail: String,
+        completed: Vec<CompletedTool>,
+        todos: Vec<TodoItem>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompletedTool {
+    pub summary: String,
+    pub is_error: bool,
+}
+
+/// Receives agent events. Called inline from the agent loop, so it should
+/// return quickly; hand work off to a channel if it can't.
+pub trait EventSink: Send + Sync {
+    fn emit(&self, event: &AgentEvent);
+}
+
+impl<F: Fn(&AgentEvent) + Send + Sync> EventSink for F {
+    fn emit(&self, event: &AgentEvent) {
+        self(event)
+    }
+}
+
+/// Forwards every event into a channel. Events are dropped once the
+/// receiver is gone.
+impl EventSink for UnboundedSender<AgentEvent> {
+    fn emit(&self, event: &AgentEvent) {
+        let _ = self.send(event.clone());
+    }
+}
+
+impl<S: EventSink + ?Sized> EventSink for Arc<S> {
+    fn emit(&self, event: &AgentEvent) {
+        (**self).emit(event)
+    }
+}
+
+/// Discards everything.
+pub struct NullSink;
+
+impl EventSink for NullSink {
+    fn emit(&self, _event: &AgentEvent) {}
+}
@@ -0,0 +1,355 @@
+use anyhow::Result;
+use globset::GlobSet;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+
+use crate::redact;
+use crate::tools::bash::ProcessGroup;
+
+/// Exit code with which a hook blocks what it was called for.
+const BLOCK_EXIT_CODE: i32 = 2;
+
+/// The `[hooks]` config table: commands to run at points in the agent loop.
+///
+/// ```toml
+/// [[hooks.post_tool_use]]
+/// matcher = "edit|write"
+/// paths = ["*.rs"]
+/// command = "cargo fmt"
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HookSettings {
+    /// Before a tool call, ahead of the permission checks.
+    pub pre_tool_use: Vec<Hook>,
+    /// After a tool call, before its result goes to the model.
+    pub post_tool_use: Vec<Hook>,
+    /// Before a prompt is sent.
+    pub user_prompt_submit: Vec<Hook>,
+    /// When the model has finished answering.
+    pub stop: Vec<Hook>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hook {
+    /// Regular expression for the tool names this hook runs for, matched
+    /// against the whole name. Unset matches every tool. Ignored for
+    /// `user_prompt_submit` and `stop`.
+    #[serde(default)]
+    pub matcher: Option<String>,
+    /// Globs for the call's `path` argument: the hook only runs for calls
+    /// with a matching path. Globs without a `/` match the file name.
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// Run with `sh -c` in the workspace root, with the event as JSON on
+    /// stdin.
+    pub command: String,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_timeout_secs() -> u64 {
+    60
+}
+
+impl Hook {
+    /// Fails if the matcher or a path glob doesn't compile.
+    pub fn check(&self) -> Result<()> {
+        CompiledHook::new(self).map(drop)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    PreToolUse,
+    PostToolUse,
+    UserPromptSubmit,
+    Stop,
+}
+
+/// What a hook may print on stdout, as JSON, when it exits with 0.
+#[derive(Debug, Default, Deserialize)]
+struct HookReply {
+    /// `"block"` to block.
+    decision: Option<String>,
+    reason: Option<String>,
+    /// Replacement arguments, for `pre_tool_use`.
+    input: Option<Value>,
+    /// Text to add to the model's context.
+    context: Option<String>,
+}
+
+/// What the hooks for one event decided, taken together.
+#[derive(Debug, Default)]
+pub struct HookOutcome {
+    /// Why a hook blocked, if one did. Later hooks don't run.
+    pub blocked: Option<String>,
+    /// Arguments to use instead of the model's, from `pre_tool_use` hooks.
+    pub input: Option<Value>,
+    /// Feedback for the model, in hook order.
+    pub context: Vec<String>,
+    /// Hooks that failed without blocking: a bad exit code, a timeout or
+    /// unreadable JSON.
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct CompiledHook {
+    hook: Hook,
+    matcher: Option<Regex>,
+    names: GlobSet,
+    paths: GlobSet,
+}
+
+impl CompiledHook {
+    fn new(hook: &Hook) -> Result<Self> {
+        let matcher = hook
+            .matcher
+            .as_deref()
+            .filter(|m| !m.is_empty() && *m != "*")
+            .map(|m| Regex::new(&format!("^(?:{})$", m)).map_err(|e| anyhow::anyhow!("invalid matcher '{}': {}", m, e)))
+            .transpose()?;
+        let (names, paths): (Vec<String>, Vec<String>) = hook.paths.iter().cloned().partition(|glob| !glob.contains('/'));
+        Ok(Self {
+            hook: hook.clone(),
+            matcher,
+            names: redact::compile_globs(&names)?,
+            paths: redact::compile_globs(&paths)?,
+        })
+    }
+
+    fn matches(&self, tool: Option<&str>, path: Option<&Path>) -> bool {
+        let Some(tool) = tool else {
+            return true;
+        };
+        if self.matcher.as_ref().is_some_and(|m| !m.is_match(tool)) {
+            return false;
+        }
+        if self.hook.paths.is_empty() {
+            return true;
+        }
+        path.is_some_and(|path| {
+            path.file_name().is_some_and(|name| self.names.is_match(name)) || self.paths.is_match(path)
+        })
+    }
+}
+
+/// The configured hooks, ready to run. A hook gets a JSON object on stdin
+/// with the event name and its details, and answers through its exit code:
+///
+/// - 0: carry on. If stdout is a JSON object, it may block with
+///   `{"decision": "block", "reason": "..."}`, replace a tool call's
+///   arguments with `{"input": {...}}`, or add `{"context": "..."}` for the
+///   model. For `user_prompt_submit`, plain stdout is added as context.
+/// - 2: block, with stderr as the reason. A blocked tool call fails with
+///   the reason, a blocked prompt isn't sent, and a blocked stop sends the
+///   reason to the model so it carries on.
+/// - anything else: the hook failed; a warning is shown and the agent
+///   carries on.
+#[derive(Debug, Clone, Default)]
+pub struct Hooks {
+    pre_tool_use: Vec<CompiledHook>,
+    post_tool_use: Vec<CompiledHook>,
+    user_prompt_submit: Vec<CompiledHook>,
+    stop: Vec<CompiledHook>,
+}
+
+impl Hooks {
+    pub fn new(settings: &HookSettings) -> Result<Self> {
+        let compile = |hooks: &[Hook]| hooks.iter().map(CompiledHook::new).collect::<Result<Vec<_>>>();
+        Ok(Self {
+            pre_tool_use: compile(&settings.pre_tool_use)?,
+            post_tool_use: compile(&settings.post_tool_use)?,
+            user_prompt_submit: compile(&settings.user_prompt_submit)?,
+            stop: compile(&settings.stop)?,
+        })
+    }
+
+    fn for_event(&self, event: HookEvent) -> &[CompiledHook] {
+        match event {
+            HookEvent::PreToolUse => &self.pre_tool_use,
+            HookEvent::PostToolUse => &self.post_tool_use,
+            HookEvent::UserPromptSubmit => &self.user_prompt_submit,
+            HookEvent::Stop => &self.stop,
+        }
+    }
+
+    /// Whether any hook runs for this event. `tool` and `path` narrow it
+    /// down for the tool events.
+    pub fn matches(&self, event: HookEvent, tool: Option<&str>, path: Option<&Path>) -> bool {
+        self.for_event(event).iter().any(|h| h.matches(tool, path))
+    }
+
+    /// Runs the matching hooks one after another in `cwd`, passing each
+    /// `payload` with an `event` field added. A `pre_tool_use` hook that
+    /// replaces the arguments passes them on to the hooks after it.
+    pub async fn run(
+        &self,
+        event: HookEvent,
+        tool: Option<&str>,
+        path: Option<&Path>,
+        mut payload: Value,
+        cwd: &Path,
+    ) -> HookOutcome {
+        let mut outcome = HookOutcome::default();
+        payload["event"] = serde_json::to_value(event).unwrap_or(Value::Null);
+
+        for compiled in self.for_event(event).iter().filter(|h| h.matches(tool, path)) {
+            let hook = &compiled.hook;
+            let output = match run_command(hook, &payload.to_string(), cwd).await {
+                Ok(output) => output,
+                Err(e) => {
+                    outcome.warnings.push(format!("Hook `{}` failed: {}", hook.command, e));
+                    continue;
+                }
+            };
+            let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+
+            match output.status.code() {
+                Some(0) => {}
+                Some(BLOCK_EXIT_CODE) => {
+                    outcome.blocked = Some(if stderr.is_empty() {
+                        format!("blocked by hook `{}`", hook.command)
+                    } else {
+                        stderr
+                    });
+                    return outcome;
+                }
+                code => {
+                    let status = code.map_or("was killed by a signal".to_string(), |c| format!("exited with {}", c));
+                    let detail = if stderr.is_empty() { String::new() } else { format!(": {}", stderr) };
+                    outcome.warnings.push(format!("Hook `{}` {}{}", hook.command, status, detail));
+                    continue;
+                }
+            }
+
+            if !stdout.starts_with('{') {
+                if event == HookEvent::UserPromptSubmit && !stdout.is_empty() {
+                    outcome.context.push(stdout);
+                }
+                continue;
+            }
+            let reply: HookReply = match serde_json::from_str(&stdout) {
+                Ok(reply) => reply,
+                Err(e) => {
+                    outcome.warnings.push(format!("Hook `{}` printed invalid JSON: {}", hook.command, e));
+                    continue;
+                }
+            };
+            if let Some(context) = reply.context.filter(|c| !c.trim().is_empty()) {
+                outcome.context.push(context);
+            }
+            if reply.decision.as_deref() == Some("block") {
+                outcome.blocked = Some(reply.reason.unwrap_or_else(|| format!("blocked by hook `{}`", hook.command)));
+                return outcome;
+            }
+            if let Some(input) = reply.input {
+                if event == HookEvent::PreToolUse && input.is_object() {
+                    payload["input"] = input.clone();
+                    outcome.input = Some(input);
+                } else {
+                    outcome.warnings.push(format!("Hook `{}` returned input it can't change", hook.command));
+                }
+            }
+        }
+        outcome
+    }
+}
+
+async fn run_command(hook: &Hook, stdin: &str, cwd: &Path) -> Result<std::process::Output> {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(&hook.command)
+        .current_dir(cwd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    // Like `bash`, keep terminal Ctrl-C for Forge.
+    #[cfg(unix)]
+    cmd.process_group(0);
+
+    let mut child = cmd.spawn()?;
+    let mut group = ProcessGroup { pgid: child.id() };
+    if let Some(mut pipe) = child.stdin.take() {
+        let stdin = stdin.to_string();
+        // A hook that doesn't read its input closes the pipe early; that's fine.
+        tokio::spawn(async move {
+            let _ = pipe.write_all(stdin.as_bytes()).await;
+        });
+    }
+
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+    let finished = tokio::time::timeout(Duration::from_secs(hook.timeout_secs), async {
+        let (read_out, read_err, status) =
+            tokio::join!(stdout.read_to_end(&mut out), stderr.read_to_end(&mut err), child.wait());
+        read_out?;
+        read_err?;
+        status
+    })
+    .await;
+    match finished {
+        Ok(status) => {
+            group.pgid = None;
+            Ok(std::process::Output {
+                status: status?,
+                stdout: out,
+                stderr: err,
+            })
+        }
+        Err(_) => {
+            // Take down anything the hook started too, then reap `sh`.
+            drop(group);
+            let _ = child.kill().await;
+            Err(anyhow::anyhow!("timed out after {}s", hook.timeout_secs))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hook(command: &str, timeout_secs: u64) -> Hook {
+        Hook {
+            matcher: None,
+            paths: Vec::new(),
+            command: command.to_string(),
+            timeout_secs,
+        }
+    }
+
+    #[tokio::test]
+    async fn passes_stdin_and_collects_output() {
+        let dir = std::env::temp_dir();
+        let output = run_command(&hook("cat; echo oops >&2; exit 3", 5), "{}", &dir).await.unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(output.stdout, b"{}");
+        assert_eq!(output.stderr, b"oops\n");
+    }
+
+    #[tokio::test]
+    async fn timeout_kills_everything_the_hook_started() {
+        let dir = std::env::temp_dir().join(format!("forge-hook-timeout-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let command = "(sleep 2; touch survived) & sleep 30";
+        let started = std::time::Instant::now();
+        let error = run_command(&hook(command, 1), "", &dir).await.unwrap_err();
+        assert!(error.to_string().contains("timed out"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(!dir.join("survived").exists(), "a background process outlived the hook");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
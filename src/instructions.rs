@@ -0,0 +1,385 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::redact::Redactor;
+use crate::workspace::{self, Workspace};
+
+/// Name of the instruction file Forge looks for.
+pub const FILE_NAME: &str = "FORGE.md";
+
+/// Imports nested deeper than this are left as they are.
+const MAX_IMPORT_DEPTH: usize = 5;
+
+/// Files longer than this are cut short, so one stray file can't crowd out
+/// the conversation.
+const MAX_FILE_BYTES: usize = 64 * 1024;
+
+/// The prompt `/init` sends to have the agent write a `FORGE.md`.
+pub const INIT_PROMPT: &str = "\
+Analyze this repository and write a FORGE.md file at the workspace root. Forge reads it into the \
+system prompt of every future session here, so it should hold what a new contributor would need to \
+be told rather than what they can see at a glance:
+
+- how to build, run, lint and test, including how to run a single test
+- the architecture: the main modules, how they fit together and where new code usually goes
+- conventions the code follows that aren't obvious, such as error handling, naming and test layout
+- anything surprising: generated files not to edit, required environment, common pitfalls
+
+Read the README, the build files and any existing contributor docs, and look at a few representative \
+source files before writing. Keep it short and specific to this repository; leave out generic advice. \
+Never include secrets. If a FORGE.md already exists, improve it instead of replacing it.";
+
+/// Where an instruction file was found. Later scopes are more specific.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    /// `~/.config/forge/FORGE.md`, for every project.
+    User,
+    /// A directory above the workspace root.
+    Parent,
+    /// The workspace root.
+    Project,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Scope::User => "user",
+            Scope::Parent => "parent directory",
+            Scope::Project => "project",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InstructionFile {
+    pub path: PathBuf,
+    pub scope: Scope,
+    /// The file's text with its imports expanded, redacted.
+    pub content: String,
+}
+
+/// The `FORGE.md` files that apply to a workspace, most general first.
+#[derive(Debug, Clone, Default)]
+pub struct Instructions {
+    pub files: Vec<InstructionFile>,
+    /// Imports that couldn't be read and files that were cut short.
+    pub warnings: Vec<String>,
+}
+
+impl Instructions {
+    /// Reads `~/.config/forge/FORGE.md` (or under `$XDG_CONFIG_HOME`), then
+    /// a `FORGE.md` in each directory above `root`, outermost first, then
+    /// the one in `root` itself.
+    ///
+    /// A line holding only `@path` is replaced by that file's contents. The
+    /// path is relative to the file the line is in; `~/` means the home
+    /// directory. Imports inside fenced code blocks are left alone, and
+    /// files matching `redaction.deny_read` are never imported.
+    ///
+    /// Project and parent files come with whatever was cloned, so they may
+    /// only import from the workspace's directories; anything else would go
+    /// to the API in the system prompt. The user file may also import from
+    /// the home directory and its own directory.
+    pub fn load(workspace: &Workspace, redactor: &Redactor) -> Self {
+        Self::load_with(workspace, redactor, user_path())
+    }
+
+    fn load_with(workspace: &Workspace, redactor: &Redactor, user_file: Option<PathBuf>) -> Self {
+        let root = workspace.root();
+        let mut candidates = Vec::new();
+        if let Some(path) = user_file {
+            candidates.push((path, Scope::User));
+        }
+        let mut parents: Vec<_> = root.ancestors().skip(1).map(|dir| (dir.join(FILE_NAME), Scope::Parent)).collect();
+        parents.reverse();
+        candidates.extend(parents);
+        candidates.push((root.join(FILE_NAME), Scope::Project));
+
+        let mut instructions = Self::default();
+        let mut seen = Vec::new();
+        for (path, scope) in candidates {
+            // The user file can also be a parent, e.g. with the workspace in
+            // `~/.config`.
+            let Ok(real) = path.canonicalize() else {
+                continue;
+            };
+            if seen.contains(&real) {
+                continue;
+            }
+            if scope == Scope::Project && !workspace.contains(&real) {
+                instructions.warnings.push(format!("{} links outside the workspace; skipped", path.display()));
+                continue;
+            }
+            seen.push(real.clone());
+            let Some(text) = read_capped(&path, &mut instructions.warnings) else {
+                continue;
+            };
+            let mut allowed: Vec<PathBuf> = workspace.dirs().map(Path::to_path_buf).collect();
+            if scope == Scope::User {
+                let home = std::env::var_os("HOME").map(PathBuf::from);
+                allowed.extend(home.and_then(|home| home.canonicalize().ok()));
+                allowed.extend(real.parent().map(Path::to_path_buf));
+            }
+            let mut loader = Loader {
+                root,
+                allowed,
+                redactor,
+                warnings: &mut instructions.warnings,
+                stack: vec![real],
+            };
+            let content = loader.expand(&text, &path, 0);
+            instructions.files.push(InstructionFile {
+                path,
+                scope,
+                content: redactor.redact(&content).into_owned(),
+            });
+        }
+        instructions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.iter().all(|f| f.content.trim().is_empty())
+    }
+
+    /// The files as a section of the system prompt, one heading per file,
+    /// or `None` if there are none.
+    pub fn prompt(&self) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut prompt = String::from(
+            "# Project instructions\n\n\
+             These instructions come from FORGE.md files written by the user and the project. \
+             Follow them. They are listed from most general to most specific; when two disagree, \
+             the later one wins.",
+        );
+        for file in self.files.iter().filter(|f| !f.content.trim().is_empty()) {
+            prompt.push_str(&format!(
+                "\n\n## {} ({})\n\n{}",
+                file.path.display(),
+                file.scope,
+                file.content.trim()
+            ));
+        }
+        Some(prompt)
+    }
+}
+
+/// `$XDG_CONFIG_HOME/forge/FORGE.md`, or `~/.config/forge/FORGE.md`.
+pub fn user_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("forge").join(FILE_NAME))
+}
+
+/// Expands the imports of one instruction file.
+struct Loader<'a> {
+    root: &'a Path,
+    /// Real directories imports may come from.
+    allowed: Vec<PathBuf>,
+    redactor: &'a Redactor,
+    warnings: &'a mut Vec<String>,
+    /// Real paths of the files being expanded, to catch import cycles.
+    stack: Vec<PathBuf>,
+}
+
+impl Loader<'_> {
+    fn expand(&mut self, text: &str, path: &Path, depth: usize) -> String {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let mut fenced = false;
+        let mut lines = Vec::new();
+        for line in text.lines() {
+            let trimmed = line.trim();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                fenced = !fenced;
+            }
+            let target = trimmed
+                .strip_prefix('@')
+                .filter(|t| !fenced && !t.is_empty() && !t.contains(char::is_whitespace));
+            match target.and_then(|target| self.import(target, dir, path, depth)) {
+                Some(imported) => lines.push(imported),
+                None => lines.push(line.to_string()),
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// The contents of `target`, expanded, or `None` to keep the line.
+    fn import(&mut self, target: &str, dir: &Path, from: &Path, depth: usize) -> Option<String> {
+        let mut warn = |problem: String| {
+            self.warnings.push(format!("{}: cannot import @{}: {}", from.display(), target, problem));
+        };
+        if depth >= MAX_IMPORT_DEPTH {
+            warn(format!("imports nest more than {} deep", MAX_IMPORT_DEPTH));
+            return None;
+        }
+        let path = dir.join(workspace::expand_home(Path::new(target)));
+        let real = match path.canonicalize() {
+            Ok(real) => real,
+            Err(e) => {
+                warn(e.to_string());
+                return None;
+            }
+        };
+        if !self.allowed.iter().any(|dir| real.starts_with(dir)) {
+            warn(format!("{} is outside the workspace", real.display()));
+            return None;
+        }
+        let relative = real.strip_prefix(self.root).unwrap_or(&real);
+        if self.redactor.denies_read(relative) || self.redactor.denies_read(&path) {
+            warn("matches redaction.deny_read".to_string());
+            return None;
+        }
+        if self.stack.contains(&real) {
+            warn("it imports itself".to_string());
+            return None;
+        }
+        let text = read_capped(&path, self.warnings)?;
+        self.stack.push(real);
+        let expanded = self.expand(&text, &path, depth + 1);
+        self.stack.pop();
+        Some(expanded)
+    }
+}
+
+/// The file's text, cut at `MAX_FILE_BYTES`. Unreadable files are
+/// reported, except for ones that simply don't exist.
+fn read_capped(path: &Path, warnings: &mut Vec<String>) -> Option<String> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            warnings.push(format!("Cannot read {}: {}", path.display(), e));
+            return None;
+        }
+    };
+    if bytes.len() > MAX_FILE_BYTES {
+        warnings.push(format!(
+            "{} is larger than {} KB; only the start is used",
+            path.display(),
+            MAX_FILE_BYTES / 1024
+        ));
+    }
+    let text = String::from_utf8_lossy(&bytes[..bytes.len().min(MAX_FILE_BYTES)]);
+    Some(text.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A fresh directory holding `files`, as `(relative path, contents)`.
+    fn tree(files: &[(&str, &str)]) -> PathBuf {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "forge-instructions-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        for (path, contents) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir.canonicalize().unwrap()
+    }
+
+    fn load(root: &Path, user_file: Option<PathBuf>) -> Instructions {
+        Instructions::load_with(&Workspace::new(root).unwrap(), &Redactor::default(), user_file)
+    }
+
+    fn project(instructions: &Instructions) -> &str {
+        &instructions.files.iter().find(|f| f.scope == Scope::Project).unwrap().content
+    }
+
+    #[test]
+    fn imports_relative_to_the_importing_file() {
+        let dir = tree(&[
+            ("ws/FORGE.md", "Intro\n@docs/build.md\nEnd"),
+            ("ws/docs/build.md", "Run make.\n@../style.md"),
+            ("ws/style.md", "Use tabs."),
+        ]);
+        let instructions = load(&dir.join("ws"), None);
+        assert_eq!(project(&instructions), "Intro\nRun make.\nUse tabs.\nEnd");
+        assert!(instructions.warnings.is_empty(), "{:?}", instructions.warnings);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn project_files_cannot_import_from_outside_the_workspace() {
+        let dir = tree(&[
+            ("ws/FORGE.md", "@../secret.txt\n@/etc/hostname\n@~/.aws/credentials"),
+            ("secret.txt", "outside"),
+        ]);
+        let instructions = load(&dir.join("ws"), None);
+        let content = project(&instructions);
+        assert!(!content.contains("outside"), "{}", content);
+        assert!(content.contains("@../secret.txt"));
+        assert!(
+            instructions.warnings.iter().any(|w| w.contains("@../secret.txt") && w.contains("outside the workspace")),
+            "{:?}",
+            instructions.warnings
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_the_workspace_are_not_followed() {
+        let dir = tree(&[("ws/FORGE.md", "@notes.md"), ("secret.txt", "outside")]);
+        std::os::unix::fs::symlink(dir.join("secret.txt"), dir.join("ws/notes.md")).unwrap();
+        let instructions = load(&dir.join("ws"), None);
+        assert_eq!(project(&instructions), "@notes.md");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn the_user_file_may_import_from_its_own_directory() {
+        let dir = tree(&[
+            ("config/FORGE.md", "@prefs.md"),
+            ("config/prefs.md", "Be brief."),
+            ("ws/FORGE.md", "@../config/prefs.md"),
+        ]);
+        let instructions = load(&dir.join("ws"), Some(dir.join("config/FORGE.md")));
+        let user = instructions.files.iter().find(|f| f.scope == Scope::User).unwrap();
+        assert_eq!(user.content, "Be brief.");
+        assert_eq!(project(&instructions), "@../config/prefs.md");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cycles_are_reported_and_kept_as_text() {
+        let dir = tree(&[("ws/FORGE.md", "top\n@a.md"), ("ws/a.md", "a\n@b.md"), ("ws/b.md", "b\n@a.md")]);
+        let instructions = load(&dir.join("ws"), None);
+        assert_eq!(project(&instructions), "top\na\nb\n@a.md");
+        assert!(instructions.warnings.iter().any(|w| w.contains("imports itself")), "{:?}", instructions.warnings);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn imports_stop_at_the_depth_limit() {
+        let mut files: Vec<(String, String)> = vec![("ws/FORGE.md".into(), "@1.md".into())];
+        for i in 1..=MAX_IMPORT_DEPTH + 1 {
+            files.push((format!("ws/{}.md", i), format!("level {}\n@{}.md", i, i + 1)));
+        }
+        let files: Vec<(&str, &str)> = files.iter().map(|(p, c)| (p.as_str(), c.as_str())).collect();
+        let dir = tree(&files);
+        let instructions = load(&dir.join("ws"), None);
+        let content = project(&instructions);
+        assert!(content.contains(&format!("level {}", MAX_IMPORT_DEPTH)));
+        assert!(!content.contains(&format!("level {}", MAX_IMPORT_DEPTH + 1)));
+        assert!(instructions.warnings.iter().any(|w| w.contains("nest more than")), "{:?}", instructions.warnings);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn imports_inside_code_fences_are_left_alone() {
+        let dir = tree(&[("ws/FORGE.md", "```\n@notes.md\n```\n@notes.md"), ("ws/notes.md", "imported")]);
+        let instructions = load(&dir.join("ws"), None);
+        assert_eq!(project(&instructions), "```\n@notes.md\n```\nimported");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,60 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+use crate::Output;
+
+/// A second Ctrl-C within this window exits instead of cancelling.
+const DOUBLE_PRESS_WINDOW: Duration = Duration::from_secs(2);
+
+/// Ctrl-C handling for the REPL. The first press cancels the turn in
+/// flight; a second press within `DOUBLE_PRESS_WINDOW` exits.
+#[derive(Clone, Default)]
+pub struct Interrupt {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    token: CancellationToken,
+    last_press: Option<Instant>,
+}
+
+impl Interrupt {
+    /// Takes over SIGINT for the process and returns the shared handle.
+    pub fn install() -> Self {
+        let interrupt = Self::default();
+        let listener = interrupt.clone();
+        tokio::spawn(async move {
+            let output = Output::new();
+            while tokio::signal::ctrl_c().await.is_ok() {
+                if listener.press() {
+                    output.error("\nExiting");
+                    std::process::exit(130);
+                }
+                output.error("\nInterrupted. Press Ctrl-C again to exit.");
+            }
+        });
+        interrupt
+    }
+
+    /// Starts a new turn: returns a fresh token that the next Ctrl-C cancels.
+    pub fn begin(&self) -> CancellationToken {
+        let mut state = self.state.lock().unwrap();
+        state.token = CancellationToken::new();
+        state.token.clone()
+    }
+
+    /// Records a Ctrl-C and cancels the current turn. Returns true if this
+    /// was the second press inside the window and the caller should exit.
+    pub fn press(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.token.cancel();
+        let now = Instant::now();
+        let exit = state
+            .last_press
+            .is_some_and(|last| now.duration_since(last) < DOUBLE_PRESS_WINDOW);
+        state.last_press = Some(now);
+        exit
+    }
+}
@@ -2,7 +2,28 @@ pub mod output;
 pub mod types;
 pub mod tools;
 pub mod agent;
+pub mod audit;
+pub mod builder;
+pub mod cache;
+pub mod config;
+pub mod egress;
+pub mod events;
+pub mod hooks;
+pub mod instructions;
+pub mod interrupt;
+pub mod permission;
+pub mod provider;
+pub mod redact;
+pub mod sandbox;
+pub mod session;
+pub mod shell;
+pub mod usage;
+pub mod workspace;
 
 pub use output::Output;
 pub use types::*;
-pub use agent::Agent;
+pub use agent::{supports_thinking, Agent, AgentLimits, AgentResult, RetryPolicy, StopReason, ThinkingSettings};
+pub use builder::AgentBuilder;
+pub use events::{AgentEvent, EventSink, NullSink};
+pub use tools::Tool;
+pub use workspace::Workspace;
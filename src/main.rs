@@ -1,26 +1,414 @@
-use inquire::Text;
-use forge::{Output, Agent};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use inquire::{InquireError, Text};
+use forge::{Output, Agent, AgentEvent, EventSink, StopReason};
+use forge::agent::ToolCall;
+use forge::audit::{self, AuditLog, AuditQuery};
+use forge::cache::HttpCache;
+use forge::config::Config;
+use forge::instructions;
+use forge::interrupt::Interrupt;
+use forge::output::{self, RunResult};
 use std::env;
+use std::io::{IsTerminal, Read};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+/// The task finished.
+const EXIT_SUCCESS: u8 = 0;
+/// The task failed: an API error, or the model could not finish.
+const EXIT_TASK_FAILED: u8 = 1;
+/// A turn, tool-call, output-token or spending limit stopped the task.
+const EXIT_LIMIT_REACHED: u8 = 2;
+/// Bad flags, bad config or a missing API key; nothing was run.
+const EXIT_CONFIG_ERROR: u8 = 3;
+/// Cancelled with Ctrl-C.
+const EXIT_INTERRUPTED: u8 = 130;
+
+#[derive(Parser)]
+#[command(name = "forge", version, about = "An AI coding agent for your terminal")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Run one task and exit. Without a value, or with `-`, the prompt is
+    /// read from stdin.
+    #[arg(short, long, value_name = "PROMPT", num_args = 0..=1, default_missing_value = "-")]
+    print: Option<String>,
+
+    /// Model ID to use.
+    #[arg(short, long)]
+    model: Option<String>,
+
+    /// Directory to work in: the workspace root file tools are confined to.
+    /// Project config is read from here.
+    #[arg(short = 'C', long, value_name = "DIR")]
+    cwd: Option<PathBuf>,
+
+    /// Model round-trips allowed per request.
+    #[arg(long, value_name = "N")]
+    max_turns: Option<u32>,
+
+    /// What the agent may do without asking.
+    #[arg(long, value_name = "MODE", value_parser = ["ask", "accept-edits", "auto", "read-only"])]
+    permission_mode: Option<String>,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    output_format: OutputFormat,
+
+    /// Serve web tools from the cache only.
+    #[arg(long)]
+    offline: bool,
+
+    /// Print token usage and cost after every request.
+    #[arg(long)]
+    status_line: bool,
+
+    /// Override any config key, e.g. `-c limits.max_tool_calls=50`.
+    #[arg(short = 'c', long = "config", value_name = "KEY=VALUE", value_parser = parse_override)]
+    overrides: Vec<(String, String)>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the effective configuration and where each value came from.
+    Config,
+    /// Show tool calls recorded in the audit log.
+    Audit(AuditArgs),
+}
+
+#[derive(Args)]
+struct AuditArgs {
+    /// Only calls from this session (an ID or the start of one).
+    #[arg(long)]
+    session: Option<String>,
+
+    /// Only calls to this tool.
+    #[arg(long)]
+    tool: Option<String>,
+
+    /// Only calls at or after TIME: Unix seconds, a duration ago like 30m,
+    /// 12h, 7d or 2w, or a UTC date like 2025-06-01 or 2025-06-01T14:30.
+    #[arg(long, value_name = "TIME")]
+    since: Option<String>,
+
+    /// Only calls before TIME, in the same formats as --since.
+    #[arg(long, value_name = "TIME")]
+    until: Option<String>,
+
+    /// Show only the last N matching calls.
+    #[arg(short = 'n', long, value_name = "N")]
+    limit: Option<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Colored, human-readable output.
+    Text,
+    /// Only the final result and its metadata, as one JSON object.
+    Json,
+    /// One JSON event per line as the agent works, then the final result.
+    StreamJson,
+}
+
+impl From<OutputFormat> for output::OutputFormat {
+    fn from(format: OutputFormat) -> Self {
+        match format {
+            OutputFormat::Text => output::OutputFormat::Text,
+            OutputFormat::Json => output::OutputFormat::Json,
+            OutputFormat::StreamJson => output::OutputFormat::StreamJson,
+        }
+    }
+}
+
+impl Cli {
+    /// Flags that map onto config keys, applied after any `-c` overrides.
+    fn config_overrides(&self) -> Vec<(String, String)> {
+        let mut overrides = self.overrides.clone();
+        let mut set = |key: &str, value: String| overrides.push((key.to_string(), value));
+        if let Some(model) = &self.model {
+            set("agent.model", model.clone());
+        }
+        if let Some(max_turns) = self.max_turns {
+            set("limits.max_turns", max_turns.to_string());
+        }
+        if let Some(mode) = &self.permission_mode {
+            set("permissions.mode", mode.clone());
+        }
+        if self.offline {
+            set("cache.offline", "true".to_string());
+        }
+        if self.status_line {
+            set("ui.status_line", "true".to_string());
+        }
+        overrides
+    }
+}
+
+/// `forge audit`: prints the matching entries, oldest first.
+fn show_audit_log(output: &Output, config: &Config, args: &AuditArgs) -> ExitCode {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let parse = |time: &Option<String>| time.as_deref().map(|t| audit::parse_time(t, now)).transpose();
+    let (since, until) = match (parse(&args.since), parse(&args.until)) {
+        (Ok(since), Ok(until)) => (since, until),
+        (Err(e), _) | (_, Err(e)) => {
+            output.error(&format!("{}", e));
+            return ExitCode::from(EXIT_CONFIG_ERROR);
+        }
+    };
+    let query = AuditQuery {
+        session: args.session.clone(),
+        tool: args.tool.clone(),
+        since,
+        until,
+    };
+
+    let log = AuditLog::new(config.audit.path.clone().unwrap_or_else(AuditLog::default_path));
+    let (mut entries, skipped) = match log.read(&query) {
+        Ok(read) => read,
+        Err(e) => {
+            output.error(&format!("Cannot read audit log {}: {}", log.path().display(), e));
+            return ExitCode::from(EXIT_TASK_FAILED);
+        }
+    };
+    if let Some(limit) = args.limit {
+        entries.drain(..entries.len().saturating_sub(limit));
+    }
+    if skipped > 0 {
+        output.emit(&AgentEvent::Warning {
+            message: format!("Skipped {} unreadable line(s) in {}", skipped, log.path().display()),
+        });
+    }
+
+    match output.format() {
+        output::OutputFormat::Text => {
+            if entries.is_empty() {
+                output.info("No matching tool calls");
+            }
+            for entry in &entries {
+                let call = ToolCall {
+                    id: entry.tool_use_id.clone(),
+                    tool: entry.tool.clone(),
+                    input: entry.input.clone(),
+                };
+                output.audit_entry(entry, &call.summary());
+            }
+        }
+        output::OutputFormat::Json => match serde_json::to_string(&entries) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                output.error(&format!("{}", e));
+                return ExitCode::from(EXIT_TASK_FAILED);
+            }
+        },
+        output::OutputFormat::StreamJson => {
+            for entry in &entries {
+                if let Ok(line) = serde_json::to_string(entry) {
+                    println!("{}", line);
+                }
+            }
+        }
+    }
+    ExitCode::from(EXIT_SUCCESS)
+}
+
+fn parse_override(arg: &str) -> Result<(String, String), String> {
+    arg.split_once('=')
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .filter(|(key, _)| !key.is_empty())
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{}'", arg))
+}
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> ExitCode {
     dotenv::dotenv().ok();
 
-    let output = Output::new();
-    output.info("Forging now...\n");
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) if e.use_stderr() => {
+            let _ = e.print();
+            return ExitCode::from(EXIT_CONFIG_ERROR);
+        }
+        // --help and --version
+        Err(e) => {
+            let _ = e.print();
+            return ExitCode::from(EXIT_SUCCESS);
+        }
+    };
 
-    let api_key = env::var("ANTHROPIC_API_KEY").unwrap_or_else(|_| {
+    let output = Output::with_format(cli.output_format.into());
+
+    if let Some(dir) = &cli.cwd {
+        if let Err(e) = env::set_current_dir(dir) {
+            output.error(&format!("Cannot change to {}: {}", dir.display(), e));
+            return ExitCode::from(EXIT_CONFIG_ERROR);
+        }
+    }
+
+    let loaded = match Config::load(&cli.config_overrides()) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            output.error(&format!("{}", e));
+            return ExitCode::from(EXIT_CONFIG_ERROR);
+        }
+    };
+    for warning in &loaded.warnings {
+        output.emit(&AgentEvent::Warning {
+            message: warning.clone(),
+        });
+    }
+
+    if let Some(Command::Config) = cli.command {
+        for (key, value, source) in loaded.entries() {
+            Output::new().config_entry(&key, &value.to_string(), &source.to_string());
+        }
+        return ExitCode::from(EXIT_SUCCESS);
+    }
+    let config = loaded.config;
+
+    if let Some(Command::Audit(args)) = &cli.command {
+        return show_audit_log(&output, &config, args);
+    }
+
+    // A prompt on stdin means a one-shot run, even without `-p`.
+    let one_shot = match cli.print.as_deref() {
+        Some("-") => Some(read_stdin_prompt()),
+        Some(prompt) => Some(prompt.to_string()),
+        None if !std::io::stdin().is_terminal() => Some(read_stdin_prompt()),
+        None => None,
+    };
+    if one_shot.as_ref().is_some_and(|prompt| prompt.trim().is_empty()) {
+        output.error("No prompt given");
+        return ExitCode::from(EXIT_CONFIG_ERROR);
+    }
+    if one_shot.is_none() && cli.output_format != OutputFormat::Text {
+        output.error("JSON output needs a one-shot prompt: use -p or pipe one on stdin");
+        return ExitCode::from(EXIT_CONFIG_ERROR);
+    }
+
+    let Ok(api_key) = env::var("ANTHROPIC_API_KEY") else {
         output.error("ANTHROPIC_API_KEY not found in environment");
         output.info("Set it with: export ANTHROPIC_API_KEY=your_key");
         output.info("Or create a .env file");
-        std::process::exit(1);
-    });
+        return ExitCode::from(EXIT_CONFIG_ERROR);
+    };
+
+    if one_shot.is_none() {
+        output.info("Forging now...\n");
+    }
+
+    let http_cache = HttpCache::new(HttpCache::default_dir(), Duration::from_secs(config.cache.ttl_secs))
+        .offline(config.cache.offline);
+    if config.cache.offline {
+        output.info("Offline mode: web tools will only use cached responses\n");
+    }
+
+    let interrupt = Interrupt::install();
+
+    output.set_show_thinking(config.thinking.show);
+    let show_status_line = config.ui.status_line;
+
+    let mut builder = Agent::builder(api_key)
+        .event_sink(output.clone())
+        .provider(config.provider)
+        .model(config.agent.model)
+        .max_tokens(config.agent.max_tokens)
+        .limits(config.limits)
+        .retry_policy(config.retry)
+        .permission_mode(config.permissions.mode)
+        .outside_access(config.workspace.outside)
+        .sandbox(config.sandbox)
+        .redaction(config.redaction)
+        .hooks(config.hooks)
+        .instructions(config.agent.instructions)
+        .interrupt(interrupt.clone())
+        .price_table(config.pricing)
+        .budget(config.budget)
+        .thinking(config.thinking)
+        .http_cache(http_cache)
+        .egress_policy(config.network)
+        .ask_fallback(std::io::stdin().is_terminal(), config.tools.ask_default);
+    for name in &config.tools.disabled {
+        builder = builder.remove_tool(name);
+    }
+    for dir in &config.workspace.additional_dirs {
+        builder = builder.add_dir(dir);
+    }
+    if config.audit.enabled {
+        builder = builder.audit_log(config.audit.path.clone().unwrap_or_else(AuditLog::default_path));
+    }
+    if let Some(prompt) = config.agent.system_prompt {
+        builder = builder.system_prompt(prompt);
+    }
+    if let Some(extra) = config.agent.append_system_prompt {
+        builder = builder.append_system_prompt(extra);
+    }
+    let mut agent = match builder.build() {
+        Ok(agent) => agent,
+        Err(e) => {
+            output.error(&format!("{}", e));
+            return ExitCode::from(EXIT_CONFIG_ERROR);
+        }
+    };
+    if one_shot.is_none() && !agent.instruction_files().is_empty() {
+        let paths: Vec<_> = agent
+            .instruction_files()
+            .iter()
+            .map(|path| agent.workspace().display(&path.display().to_string()))
+            .collect();
+        output.info(&format!("Instructions: {}\n", paths.join(", ")));
+    }
+
+    if let Some(prompt) = one_shot {
+        output.start(agent.session_id(), agent.model());
+        let started = Instant::now();
+        let result = agent.run(prompt.trim()).await;
+        if show_status_line {
+            output.status_line(agent.request_usage(), agent.request_cost(), agent.cost());
+        }
+        if let Err(e) = agent.save_session() {
+            output.error(&format!("Failed to save session: {}", e));
+        }
 
-    let mut agent = Agent::new(api_key);
+        let code = match result.as_ref().map(|r| r.stop_reason) {
+            Ok(StopReason::Completed) => EXIT_SUCCESS,
+            Ok(StopReason::Interrupted) => EXIT_INTERRUPTED,
+            Ok(StopReason::MaxTokens | StopReason::MaxTurns | StopReason::MaxToolCalls | StopReason::BudgetExceeded) => {
+                EXIT_LIMIT_REACHED
+            }
+            Err(e) => {
+                output.error(&format!("Error: {}", e));
+                EXIT_TASK_FAILED
+            }
+        };
+        output.result(&RunResult {
+            stop_reason: result.as_ref().ok().map(|r| r.stop_reason),
+            is_error: code != EXIT_SUCCESS,
+            result: agent.final_text().to_string(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            session_id: agent.session_id().to_string(),
+            model: agent.model().to_string(),
+            duration_ms: started.elapsed().as_millis() as u64,
+            usage: *agent.request_usage(),
+            cost_usd: agent.request_cost(),
+        });
+        return ExitCode::from(code);
+    }
 
     loop {
         let input = match Text::new("you>").prompt() {
             Ok(i) => i,
+            // The prompt reads Ctrl-C as a key, so no signal fires here.
+            Err(InquireError::OperationInterrupted) => {
+                if interrupt.press() {
+                    break;
+                }
+                output.info("Press Ctrl-C again to exit");
+                continue;
+            }
             Err(_) => break,
         };
 
@@ -34,18 +422,46 @@ async fn main() -> anyhow::Result<()> {
             break;
         }
 
-        println!();
+        if input == "/cost" {
+            output.cost_summary(agent.model(), agent.api_calls(), agent.usage(), agent.cost());
+            continue;
+        }
+
+        if input == "/thinking" {
+            let shown = output.toggle_thinking();
+            output.info(if shown { "Thinking will be shown" } else { "Thinking will be hidden" });
+            continue;
+        }
+
+        // Has the agent draft a FORGE.md; it is read at the next start.
+        let input = if input == "/init" {
+            instructions::INIT_PROMPT
+        } else {
+            input
+        };
 
-        // Add user message to conversation history
-        agent.add_user_message(input);
+        println!();
 
         // Process the request with full context
-        if let Err(e) = agent.process().await {
+        if let Err(e) = agent.run(input).await {
             output.error(&format!("Error: {}", e));
         }
+        if show_status_line {
+            output.status_line(agent.request_usage(), agent.request_cost(), agent.cost());
+        }
+
+        if let Err(e) = agent.save_session() {
+            output.error(&format!("Failed to save session: {}", e));
+        }
 
         println!();
     }
 
-    Ok(())
+    ExitCode::from(EXIT_SUCCESS)
+}
+
+fn read_stdin_prompt() -> String {
+    let mut prompt = String::new();
+    let _ = std::io::stdin().read_to_string(&mut prompt);
+    prompt
 }
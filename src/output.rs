@@ -1,38 +1,249 @@
 use owo_colors::OwoColorize;
 use console::Term;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use crate::agent::StopReason;
+use crate::audit::{self, AuditDecision, AuditEntry, AuditStatus};
+use crate::events::{AgentEvent, CompletedTool, EventSink};
+use crate::shell::{Classification, CommandClass};
+use crate::tools::OutputStream;
+use crate::types::{TodoItem, TodoStatus};
+use crate::usage::Usage;
 
+/// Bumped whenever a `stream-json` line or the final result changes shape
+/// in a way that could break a parser.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Colored, human-readable output.
+    #[default]
+    Text,
+    /// Only the final result, as one JSON object.
+    Json,
+    /// Every event as one JSON object per line, then the final result.
+    StreamJson,
+}
+
+/// The final result of a one-shot run, printed in the JSON formats.
+#[derive(Debug, Serialize)]
+pub struct RunResult {
+    /// `None` when the run failed with an error.
+    pub stop_reason: Option<StopReason>,
+    pub is_error: bool,
+    /// Text of the model's last reply.
+    pub result: String,
+    pub error: Option<String>,
+    pub session_id: String,
+    pub model: String,
+    pub duration_ms: u64,
+    pub usage: Usage,
+    pub cost_usd: Option<f64>,
+}
+
+/// The terminal renderer. As an `EventSink` it draws agent events in the
+/// chosen format; the other methods cover what the binary prints itself.
+#[derive(Clone)]
 pub struct Output {
     pub term: Term,
+    format: OutputFormat,
+    show_thinking: Arc<AtomicBool>,
+    headers: Arc<Mutex<ToolHeaders>>,
+}
+
+/// Headers of tool calls that haven't finished. Parallel calls all start
+/// before any finishes, so a result is labeled again unless its own header
+/// was the last one printed.
+#[derive(Default)]
+struct ToolHeaders {
+    last: Option<String>,
+    pending: HashMap<String, String>,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Output {
     pub fn new() -> Self {
+        Self::with_format(OutputFormat::Text)
+    }
+
+    /// In the JSON formats stdout carries only JSON: the text-only methods
+    /// print nothing and errors go to stderr uncolored.
+    pub fn with_format(format: OutputFormat) -> Self {
         Self {
             term: Term::stdout(),
+            format,
+            show_thinking: Arc::new(AtomicBool::new(true)),
+            headers: Arc::default(),
         }
     }
 
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    fn is_text(&self) -> bool {
+        self.format == OutputFormat::Text
+    }
+
+    /// Whether thinking is printed or collapsed to one line. Shared by
+    /// every clone of this `Output`.
+    pub fn set_show_thinking(&self, show: bool) {
+        self.show_thinking.store(show, Ordering::Relaxed);
+    }
+
+    /// Flips thinking display and returns the new setting.
+    pub fn toggle_thinking(&self) -> bool {
+        !self.show_thinking.fetch_xor(true, Ordering::Relaxed)
+    }
+
     pub fn success(&self, msg: &str) {
-        println!("{}", msg.green().bold());
+        if self.is_text() {
+            println!("{}", msg.green().bold());
+        }
     }
 
     pub fn error(&self, msg: &str) {
-        eprintln!("{}", msg.red().bold());
+        match self.format {
+            OutputFormat::Text => eprintln!("{}", msg.red().bold()),
+            OutputFormat::Json => eprintln!("{}", msg.trim()),
+            OutputFormat::StreamJson => self.emit(&AgentEvent::Error {
+                message: msg.trim().to_string(),
+            }),
+        }
     }
 
     pub fn info(&self, msg: &str) {
-        println!("{}", msg.blue());
+        if self.is_text() {
+            println!("{}", msg.blue());
+        }
     }
 
     pub fn tool_header(&self, tool: &str) {
-        println!("{}", format!("→ {}", tool).cyan().bold());
+        if self.is_text() {
+            println!("{}", format!("→ {}", tool).cyan().bold());
+        }
+    }
+
+    /// The risk class of a `bash` command, under its tool header.
+    pub fn command_class(&self, classification: &Classification) {
+        if !self.is_text() {
+            return;
+        }
+        let line = format!("  {}", classification.describe());
+        match classification.class {
+            CommandClass::Destructive => println!("{}", line.red().bold()),
+            CommandClass::Network | CommandClass::Mutating => println!("{}", line.yellow()),
+            CommandClass::ReadOnly | CommandClass::BuildTest => println!("{}", line.dimmed()),
+        }
+    }
+
+    /// First line of a `stream-json` run.
+    pub fn start(&self, session_id: &str, model: &str) {
+        if self.format == OutputFormat::StreamJson {
+            println!("{}", json!({
+                "type": "start",
+                "schema_version": SCHEMA_VERSION,
+                "session_id": session_id,
+                "model": model,
+            }));
+        }
+    }
+
+    /// The closing object in both JSON formats; nothing in text mode.
+    pub fn result(&self, result: &RunResult) {
+        if self.is_text() {
+            return;
+        }
+        let mut value = json!({ "type": "result", "schema_version": SCHEMA_VERSION });
+        if let (Value::Object(fields), Ok(Value::Object(result))) = (&mut value, serde_json::to_value(result)) {
+            fields.extend(result);
+        }
+        println!("{}", value);
+    }
+
+    /// Terminal rendering of one agent event.
+    fn render(&self, event: &AgentEvent) {
+        match event {
+            AgentEvent::Thinking { text, redacted } => {
+                self.thinking(if *redacted { "[redacted]" } else { text });
+            }
+            AgentEvent::AssistantText { text } => {
+                println!("{}", text);
+                println!();
+            }
+            AgentEvent::ToolStart { id, summary, classification, index, total, .. } => {
+                let header = format!("[{}/{}] {}", index, total, summary);
+                self.tool_header(&header);
+                if let Ok(mut headers) = self.headers.lock() {
+                    headers.last = Some(id.clone());
+                    headers.pending.insert(id.clone(), header);
+                }
+                if let Some(classification) = classification {
+                    self.command_class(classification);
+                }
+            }
+            AgentEvent::ToolOutput { stream, line, .. } => match stream {
+                OutputStream::Stdout => println!("{}", line),
+                OutputStream::Stderr => eprintln!("{}", line),
+            },
+            AgentEvent::ToolResult { id, content, is_error, .. } => {
+                if let Ok(mut headers) = self.headers.lock() {
+                    let header = headers.pending.remove(id);
+                    if headers.last.as_ref() != Some(id) {
+                        if let Some(header) = header {
+                            println!("{}", format!("← {}", header).cyan());
+                        }
+                    }
+                    headers.last = None;
+                }
+                if *is_error {
+                    self.error(&format!("✗ Error: {}", content));
+                } else {
+                    if !content.is_empty() {
+                        println!("{}", content);
+                    }
+                    self.success("✓ Done");
+                }
+                println!();
+            }
+            AgentEvent::Todos { todos } => self.todo_list(todos),
+            AgentEvent::Usage { .. } => {}
+            AgentEvent::Warning { message }
+            | AgentEvent::Error { message }
+            | AgentEvent::Stopped { message, .. } => self.error(message),
+            AgentEvent::BudgetExceeded { detail, completed, todos } => self.budget_stop(detail, completed, todos),
+        }
+    }
+
+    /// Extended thinking, dimmed. When hidden it collapses to one line.
+    fn thinking(&self, content: &str) {
+        if self.show_thinking.load(Ordering::Relaxed) {
+            println!("{}", "✻ Thinking".dimmed().bold());
+            println!("{}", content.dimmed().italic());
+        } else {
+            println!("{}", format!("✻ Thinking ({} chars hidden, /thinking to show)", content.chars().count()).dimmed());
+        }
+        println!();
     }
 
     pub fn tool_output(&self, content: &str) {
-        println!("{}", content);
+        if self.is_text() {
+            println!("{}", content);
+        }
     }
 
     pub fn diff(&self, diff_text: &str) {
+        if !self.is_text() {
+            return;
+        }
         for line in diff_text.lines() {
             match line.chars().next() {
                 Some('+') => println!("{}", line.green()),
@@ -44,6 +255,137 @@ impl Output {
     }
 
     pub fn list_item(&self, index: usize, content: &str) {
+        if !self.is_text() {
+            return;
+        }
         println!("{} {}", format!("{}.", index).cyan().bold(), content);
     }
+
+    pub fn todo_list(&self, todos: &[TodoItem]) {
+        if !self.is_text() {
+            return;
+        }
+        self.tool_header("Todos");
+        for todo in todos {
+            match todo.status {
+                TodoStatus::Pending => println!("  ☐ {}", todo.content),
+                TodoStatus::InProgress => println!("  {} {}", "◐".yellow(), todo.content.yellow().bold()),
+                TodoStatus::Completed => println!("  {} {}", "☑".green(), todo.content.dimmed().strikethrough()),
+            }
+        }
+    }
+
+    /// One dimmed line after a request: its tokens and cost, plus the
+    /// session total.
+    pub fn status_line(&self, usage: &Usage, cost: Option<f64>, session_cost: Option<f64>) {
+        if !self.is_text() {
+            return;
+        }
+        let mut line = format!(
+            "{} in · {} out",
+            format_tokens(usage.input_tokens),
+            format_tokens(usage.output_tokens)
+        );
+        if usage.cache_read_input_tokens + usage.cache_creation_input_tokens > 0 {
+            line.push_str(&format!(
+                " · cache {} read / {} write",
+                format_tokens(usage.cache_read_input_tokens),
+                format_tokens(usage.cache_creation_input_tokens)
+            ));
+        }
+        if let (Some(cost), Some(session_cost)) = (cost, session_cost) {
+            line.push_str(&format!(" · ${:.4} (session ${:.4})", cost, session_cost));
+        }
+        println!("{}", line.dimmed());
+    }
+
+    /// Explains a budget stop and lists what the request got done.
+    pub fn budget_stop(&self, detail: &str, done: &[CompletedTool], todos: &[TodoItem]) {
+        self.error(&format!("Stopped: budget limit reached ({})", detail));
+        if done.is_empty() {
+            self.info("No tools were run for this request.");
+        } else {
+            self.tool_header("Completed before stopping");
+            for (i, tool) in done.iter().enumerate() {
+                let mark = if tool.is_error { "✗".red().to_string() } else { "✓".green().to_string() };
+                self.list_item(i + 1, &format!("{} {}", mark, tool.summary));
+            }
+        }
+        if !todos.is_empty() {
+            self.todo_list(todos);
+        }
+    }
+
+    /// One line of `forge config`: the value, then where it was set.
+    pub fn config_entry(&self, key: &str, value: &str, source: &str) {
+        println!("{} = {}  {}", key.cyan(), value, format!("# {}", source).dimmed());
+    }
+
+    /// One line of `forge audit`.
+    pub fn audit_entry(&self, entry: &AuditEntry, summary: &str) {
+        let decision = match entry.decision {
+            AuditDecision::Allowed => "allowed".dimmed().to_string(),
+            AuditDecision::Approved => "approved".green().to_string(),
+            AuditDecision::Denied => "denied".red().to_string(),
+            AuditDecision::Rejected => "rejected".yellow().to_string(),
+        };
+        let status = match (entry.status, entry.exit_code) {
+            (AuditStatus::Ok, Some(code)) | (AuditStatus::Error, Some(code)) => format!("exit {}", code),
+            (AuditStatus::Ok, None) => "ok".to_string(),
+            (AuditStatus::Error, None) => "error".red().to_string(),
+            (AuditStatus::Interrupted, _) => "interrupted".yellow().to_string(),
+        };
+        println!(
+            "{}  {}  {}  {}  {:>6}ms  {}",
+            audit::format_time(entry.timestamp).dimmed(),
+            entry.session_id.cyan(),
+            decision,
+            status,
+            entry.duration_ms,
+            summary,
+        );
+    }
+
+    pub fn cost_summary(&self, model: &str, api_calls: u32, usage: &Usage, cost: Option<f64>) {
+        if !self.is_text() {
+            return;
+        }
+        self.tool_header("Session usage");
+        println!("  Model:        {}", model);
+        println!("  API calls:    {}", api_calls);
+        println!("  Input:        {} tokens", usage.input_tokens);
+        println!("  Output:       {} tokens", usage.output_tokens);
+        println!("  Cache write:  {} tokens", usage.cache_creation_input_tokens);
+        println!("  Cache read:   {} tokens", usage.cache_read_input_tokens);
+        match cost {
+            Some(cost) => println!("  Cost:         {}", format!("${:.4}", cost).bold()),
+            None => println!("  Cost:         unknown (no price for {})", model),
+        }
+    }
+}
+
+fn format_tokens(count: u64) -> String {
+    if count >= 1000 {
+        format!("{:.1}k", count as f64 / 1000.0)
+    } else {
+        count.to_string()
+    }
+}
+
+impl EventSink for Output {
+    fn emit(&self, event: &AgentEvent) {
+        match self.format {
+            OutputFormat::Text => self.render(event),
+            OutputFormat::Json => {
+                if let AgentEvent::Error { message } = event {
+                    eprintln!("{}", message);
+                }
+            }
+            OutputFormat::StreamJson => {
+                if let Ok(line) = serde_json::to_string(event) {
+                    println!("{}", line);
+                }
+            }
+        }
+    }
 }
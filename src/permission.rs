@@ -0,0 +1,147 @@
+use anyhow::Result;
+use inquire::{Confirm, InquireError};
+use serde::{Deserialize, Serialize};
+
+use crate::shell::{Classification, CommandClass};
+use crate::types::ToolError;
+
+/// Tools that only talk to the user or update Forge's own state.
+const NO_SIDE_EFFECTS: &[&str] = &["ask", "todo"];
+
+/// Tools that change files but nothing else.
+const EDIT_TOOLS: &[&str] = &["write", "edit"];
+
+/// How much the agent may do without asking. Read-only tools are always
+/// allowed; this decides what happens to everything else.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PermissionMode {
+    /// Confirm every tool call with side effects.
+    Ask,
+    /// Allow file edits, confirm shell commands.
+    AcceptEdits,
+    /// Allow everything.
+    #[default]
+    Auto,
+    /// Refuse anything with side effects.
+    ReadOnly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Allow,
+    Ask,
+    Deny(String),
+}
+
+impl PermissionMode {
+    pub fn check(&self, tool: &str, read_only: bool) -> Decision {
+        if read_only || NO_SIDE_EFFECTS.contains(&tool) {
+            return Decision::Allow;
+        }
+        match self {
+            PermissionMode::Auto => Decision::Allow,
+            PermissionMode::AcceptEdits if EDIT_TOOLS.contains(&tool) => Decision::Allow,
+            PermissionMode::Ask | PermissionMode::AcceptEdits => Decision::Ask,
+            PermissionMode::ReadOnly => {
+                Decision::Deny(format!("Permission denied: {} is not allowed in read-only mode", tool))
+            }
+        }
+    }
+
+    /// Like `check`, for a `bash` command that has been classified.
+    /// Read-only commands run in every mode, build and test commands run
+    /// with edits accepted, and destructive ones always ask.
+    pub fn check_command(&self, command: &Classification) -> Decision {
+        match (self, command.class) {
+            (_, CommandClass::ReadOnly) => Decision::Allow,
+            (PermissionMode::ReadOnly, _) => Decision::Deny(format!(
+                "Permission denied: bash command is {}, which is not allowed in read-only mode",
+                command.describe()
+            )),
+            (_, CommandClass::Destructive) => Decision::Ask,
+            (PermissionMode::Auto, _) => Decision::Allow,
+            (PermissionMode::AcceptEdits, CommandClass::BuildTest) => Decision::Allow,
+            (PermissionMode::Ask | PermissionMode::AcceptEdits, _) => Decision::Ask,
+        }
+    }
+}
+
+/// Asks the user to approve a tool call. Without a terminal nothing can be
+/// approved, so the call is refused.
+pub fn confirm(summary: &str, interactive: bool) -> Result<bool> {
+    if !interactive {
+        return Err(ToolError::Denied(format!(
+            "Permission denied: {} needs approval and no user is available",
+            summary
+        ))
+        .into());
+    }
+    match Confirm::new(&format!("Allow {}?", summary)).with_default(false).prompt() {
+        Ok(allowed) => Ok(allowed),
+        Err(InquireError::OperationCanceled | InquireError::OperationInterrupted) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::classify;
+
+    /// Commands that used to pass as read-only and so ran in every mode.
+    const BYPASSES: &[&str] = &[
+        "trap 'rm -rf ~' EXIT",
+        "alias ls='rm -rf ~'",
+        "export GIT_PAGER='rm -rf ~'",
+        "fd -x rm {}",
+        "rg --pre sh TODO",
+        "git -c core.pager='rm -rf ~' log",
+        "git difftool -x ./evil",
+        "git grep --open-files-in-pager=./evil TODO",
+        "man -P 'rm -rf ~' ls",
+        "sort --compress-program=sh big.txt",
+        "xxd -r dump.hex out.bin",
+        "iconv -o out.txt notes.txt",
+        "git log --output=notes.txt",
+        "echo ${x:-$(rm -rf ~)}",
+        "(( x = $(rm -rf ~) ))",
+        "env -S 'rm -rf /'",
+        "less -o log.txt notes.txt",
+        "history -c",
+        "printf -v PAGER 'rm -rf ~'",
+        "file -C -m magic",
+        "cat </dev/tcp/1.2.3.4/80",
+    ];
+
+    #[test]
+    fn read_only_commands_run_in_every_mode() {
+        for mode in [PermissionMode::Ask, PermissionMode::AcceptEdits, PermissionMode::Auto, PermissionMode::ReadOnly] {
+            assert_eq!(mode.check_command(&classify("git status")), Decision::Allow, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn bypasses_are_denied_in_read_only_mode() {
+        for command in BYPASSES {
+            let decision = PermissionMode::ReadOnly.check_command(&classify(command));
+            assert!(matches!(decision, Decision::Deny(_)), "{:?} got {:?}", command, decision);
+        }
+    }
+
+    #[test]
+    fn bypasses_ask_when_edits_are_not_auto_approved() {
+        for mode in [PermissionMode::Ask, PermissionMode::AcceptEdits] {
+            for command in BYPASSES {
+                assert_eq!(mode.check_command(&classify(command)), Decision::Ask, "{:?} in {:?}", command, mode);
+            }
+        }
+    }
+
+    #[test]
+    fn destructive_bypasses_ask_even_in_auto_mode() {
+        for command in ["trap 'rm -rf ~' EXIT", "echo ${x:-$(rm -rf ~)}", "env -S 'rm -rf /'"] {
+            assert_eq!(PermissionMode::Auto.check_command(&classify(command)), Decision::Ask, "{:?}", command);
+        }
+    }
+}
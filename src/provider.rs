@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+const ANTHROPIC_BASE_URL: &str = "https://api.anthropic.com";
+
+/// The backend that serves the Messages API, from the `[provider]` config
+/// table; defaults to Anthropic.
+///
+/// Anything other than `anthropic` is treated as a compatible proxy or
+/// gateway, which may not understand Anthropic-only request fields, so
+/// prompt caching is off for those unless `prompt_caching = true` is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Provider {
+    pub name: String,
+    pub base_url: String,
+    pub prompt_caching: Option<bool>,
+}
+
+impl Default for Provider {
+    fn default() -> Self {
+        Self::anthropic()
+    }
+}
+
+impl Provider {
+    pub fn anthropic() -> Self {
+        Self {
+            name: "anthropic".to_string(),
+            base_url: ANTHROPIC_BASE_URL.to_string(),
+            prompt_caching: None,
+        }
+    }
+
+    pub fn messages_url(&self) -> String {
+        format!("{}/v1/messages", self.base_url.trim_end_matches('/'))
+    }
+
+    pub fn caching_enabled(&self) -> bool {
+        self.prompt_caching.unwrap_or(self.name == "anthropic")
+    }
+}
+
+/// Marks cache breakpoints on a serialized Messages API request: the system
+/// prompt, the last tool definition, and the last block of the two most
+/// recent user messages. The cache is a prefix cache (tools, then system,
+/// then messages), so the breakpoint on the previous user message lets this
+/// call read what the last call wrote while the newest one writes for the
+/// next. That is four breakpoints, the most the API allows.
+pub fn add_cache_breakpoints(request: &mut Value) {
+    let ephemeral = json!({ "type": "ephemeral" });
+
+    if let Some(system) = request.get_mut("system") {
+        if let Some(text) = system.as_str().filter(|t| !t.is_empty()) {
+            *system = json!([{ "type": "text", "text": text, "cache_control": ephemeral }]);
+        }
+    }
+
+    if let Some(last_tool) = request
+        .get_mut("tools")
+        .and_then(Value::as_array_mut)
+        .and_then(|tools| tools.last_mut())
+    {
+        last_tool["cache_control"] = ephemeral.clone();
+    }
+
+    if let Some(messages) = request.get_mut("messages").and_then(Value::as_array_mut) {
+        for message in messages
+            .iter_mut()
+            .rev()
+            .filter(|m| m["role"] == "user")
+            .take(2)
+        {
+            if let Some(block) = message
+                .get_mut("content")
+                .and_then(Value::as_array_mut)
+                .and_then(|blocks| blocks.last_mut())
+            {
+                block["cache_control"] = ephemeral.clone();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count_breakpoints(value: &Value) -> usize {
+        match value {
+            Value::Object(map) => {
+                usize::from(map.contains_key("cache_control")) + map.values().map(count_breakpoints).sum::<usize>()
+            }
+            Value::Array(items) => items.iter().map(count_breakpoints).sum(),
+            _ => 0,
+        }
+    }
+
+    fn text(text: &str) -> Value {
+        json!({ "type": "text", "text": text })
+    }
+
+    #[test]
+    fn marks_system_last_tool_and_two_latest_user_messages() {
+        let mut request = json!({
+            "system": "You are helpful.",
+            "tools": [{ "name": "read" }, { "name": "write" }, { "name": "bash" }],
+            "messages": [
+                { "role": "user", "content": [text("first")] },
+                { "role": "assistant", "content": [text("ok")] },
+                { "role": "user", "content": [text("tool result"), text("second")] },
+                { "role": "assistant", "content": [text("ok")] },
+                { "role": "user", "content": [text("third")] },
+            ],
+        });
+        add_cache_breakpoints(&mut request);
+
+        assert_eq!(count_breakpoints(&request), 4);
+        assert_eq!(request["system"][0]["text"], "You are helpful.");
+        assert!(request["system"][0].get("cache_control").is_some());
+        assert!(request["tools"][2].get("cache_control").is_some());
+        assert!(request["tools"][0].get("cache_control").is_none());
+        let messages = &request["messages"];
+        assert!(messages[4]["content"][0].get("cache_control").is_some());
+        assert!(messages[2]["content"][1].get("cache_control").is_some());
+        assert!(messages[2]["content"][0].get("cache_control").is_none());
+        assert_eq!(count_breakpoints(&messages[0]) + count_breakpoints(&messages[1]) + count_breakpoints(&messages[3]), 0);
+    }
+
+    #[test]
+    fn never_exceeds_four_breakpoints() {
+        let messages: Vec<Value> = (0..20)
+            .map(|i| json!({ "role": if i % 2 == 0 { "user" } else { "assistant" }, "content": [text("turn")] }))
+            .collect();
+        let mut request = json!({ "system": "s", "tools": [{ "name": "read" }], "messages": messages });
+        add_cache_breakpoints(&mut request);
+        assert_eq!(count_breakpoints(&request), 4);
+
+        // Marking an already marked request again moves nothing.
+        add_cache_breakpoints(&mut request);
+        assert_eq!(count_breakpoints(&request), 4);
+    }
+
+    #[test]
+    fn skips_what_is_missing() {
+        let mut request = json!({
+            "system": "",
+            "tools": [],
+            "messages": [{ "role": "user", "content": [text("only")] }],
+        });
+        add_cache_breakpoints(&mut request);
+        assert_eq!(request["system"], "");
+        assert_eq!(count_breakpoints(&request), 1);
+        assert!(request["messages"][0]["content"][0].get("cache_control").is_some());
+    }
+}
@@ -0,0 +1,572 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+use crate::workspace::Workspace;
+
+/// Scratch directories commands may always write to, when they exist.
+const TEMP_DIRS: &[&str] = &["/tmp", "/var/tmp", "/dev/shm"];
+
+/// Device files commands may always write to.
+const WRITABLE_DEVICES: &[&str] = &["/dev/null", "/dev/zero", "/dev/full", "/dev/tty"];
+
+/// The `[sandbox]` config table. Off unless `enabled` is set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SandboxPolicy {
+    /// Run `bash` commands in the sandbox.
+    pub enabled: bool,
+    /// Let sandboxed commands use the network without asking.
+    pub allow_network: bool,
+    /// Directories besides the workspace and temp dirs that commands may
+    /// write to, e.g. a build cache. Relative ones are taken from the root.
+    pub writable_dirs: Vec<PathBuf>,
+}
+
+/// Which kernel features the sandbox could use on this machine.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Support {
+    /// Unprivileged user and network namespaces, used to take the network
+    /// away while keeping loopback.
+    pub namespaces: bool,
+    /// Landlock ABI version, used to limit writes.
+    pub landlock_abi: Option<u32>,
+    /// Seccomp filters, used to refuse system calls commands never need.
+    pub seccomp: bool,
+}
+
+/// Confines `bash` commands on Linux. Writes are limited to the workspace,
+/// the temp dirs and any configured directories (Landlock); the network is
+/// taken away by running in a fresh network namespace, or by refusing
+/// internet sockets when namespaces are unavailable; and a seccomp filter
+/// refuses calls like `ptrace`, `mount` and `bpf`. Reading is not limited.
+///
+/// Each layer is used only if the kernel supports it; `gaps` says what is
+/// missing so it can be reported instead of silently skipped.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    writable: Vec<PathBuf>,
+    allow_network: bool,
+    support: Support,
+}
+
+impl Sandbox {
+    /// Probes the kernel once; fails if a configured directory doesn't exist.
+    pub fn new(policy: &SandboxPolicy, workspace: &Workspace) -> Result<Self> {
+        let mut writable: Vec<PathBuf> = workspace.dirs().map(Path::to_path_buf).collect();
+        for dir in &policy.writable_dirs {
+            let path = workspace.resolve(&dir.to_string_lossy());
+            let real = path
+                .canonicalize()
+                .map_err(|e| anyhow::anyhow!("Invalid sandbox.writable_dirs entry {}: {}", dir.display(), e))?;
+            writable.push(real);
+        }
+        let temp = std::env::temp_dir();
+        for dir in TEMP_DIRS.iter().map(Path::new).chain([temp.as_path()]) {
+            if let Ok(real) = dir.canonicalize() {
+                writable.push(real);
+            }
+        }
+        writable.sort();
+        writable.dedup();
+
+        Ok(Self {
+            writable,
+            allow_network: policy.allow_network,
+            support: probe(),
+        })
+    }
+
+    /// The same sandbox with the network allowed, for an approved
+    /// escalation.
+    pub fn with_network(mut self) -> Self {
+        self.allow_network = true;
+        self
+    }
+
+    pub fn support(&self) -> Support {
+        self.support
+    }
+
+    /// Protections that are configured but that this machine can't
+    /// provide, in words for the user.
+    pub fn gaps(&self) -> Vec<String> {
+        if !cfg!(target_os = "linux") {
+            return vec!["the sandbox needs Linux; commands run without it".to_string()];
+        }
+        let mut gaps = Vec::new();
+        if self.support.landlock_abi.is_none() {
+            gaps.push("Landlock is unavailable, so commands can write anywhere you can".to_string());
+        }
+        if !self.allow_network && !self.support.namespaces {
+            gaps.push(if self.support.seccomp {
+                "user namespaces are unavailable, so network access is blocked with seccomp \
+                 and commands can't use localhost either"
+                    .to_string()
+            } else {
+                "user namespaces and seccomp are unavailable, so commands keep network access".to_string()
+            });
+        }
+        if !self.support.seccomp {
+            gaps.push("seccomp is unavailable, so system calls are not filtered".to_string());
+        }
+        gaps
+    }
+
+    /// What the sandbox takes away, for tool results.
+    pub fn describe(&self) -> String {
+        let network = if self.allow_network { "network allowed" } else { "network off" };
+        format!("{}, writes limited to the workspace and temp dirs", network)
+    }
+
+    /// Makes `cmd` enter the sandbox between fork and exec. Everything the
+    /// child needs is prepared here, so the child only makes system calls.
+    pub(crate) fn apply(&self, cmd: &mut Command) -> io::Result<()> {
+        #[cfg(target_os = "linux")]
+        {
+            let setup = linux::Setup::prepare(self)?;
+            unsafe {
+                cmd.pre_exec(move || setup.enter());
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = cmd;
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn probe() -> Support {
+    Support {
+        namespaces: linux::namespaces_available(),
+        landlock_abi: linux::landlock_abi(),
+        seccomp: linux::seccomp_available(),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn probe() -> Support {
+    Support::default()
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::fs::OpenOptions;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::process::CommandExt;
+    use std::path::Path;
+
+    use super::{Sandbox, WRITABLE_DEVICES};
+
+    // From linux/landlock.h; libc doesn't define these.
+    const LANDLOCK_CREATE_RULESET_VERSION: libc::c_uint = 1;
+    const LANDLOCK_RULE_PATH_BENEATH: libc::c_uint = 1;
+    const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+    const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+    /// `MAKE_CHAR` through `MAKE_SYM`.
+    const ACCESS_FS_MAKE_ANY: u64 = 0b111_1111 << 6;
+    /// Renames and links across directories; ABI 2.
+    const ACCESS_FS_REFER: u64 = 1 << 13;
+    /// ABI 3.
+    const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: libc::c_int,
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xc000_003e;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+    /// Never needed by build tools, and useful for getting out of a
+    /// sandbox or into other processes.
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    const DENIED_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_setns,
+        libc::SYS_unshare,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_keyctl,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_kexec_load,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_reboot,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+        // io_uring operations bypass seccomp.
+        libc::SYS_io_uring_setup,
+    ];
+
+    /// What the child does before exec, prepared in the parent.
+    pub struct Setup {
+        /// `/proc/self/uid_map` and `gid_map` contents; `None` skips the
+        /// namespaces.
+        id_maps: Option<(Vec<u8>, Vec<u8>)>,
+        ruleset: Option<OwnedFd>,
+        filter: Vec<libc::sock_filter>,
+    }
+
+    // The filter holds only plain integers.
+    unsafe impl Send for Setup {}
+    unsafe impl Sync for Setup {}
+
+    impl Setup {
+        pub fn prepare(sandbox: &Sandbox) -> io::Result<Self> {
+            let support = sandbox.support;
+            let use_namespaces = support.namespaces && !sandbox.allow_network;
+            let ruleset = support
+                .landlock_abi
+                .map(|abi| ruleset(abi, &sandbox.writable))
+                .transpose()?;
+            let filter = if support.seccomp {
+                filter(!sandbox.allow_network && !use_namespaces)
+            } else {
+                Vec::new()
+            };
+            Ok(Self {
+                id_maps: use_namespaces.then(id_maps),
+                ruleset,
+                filter,
+            })
+        }
+
+        /// Runs in the forked child: no allocation, only system calls.
+        pub fn enter(&self) -> io::Result<()> {
+            unsafe {
+                if let Some((uid_map, gid_map)) = &self.id_maps {
+                    enter_namespaces(uid_map, gid_map)?;
+                }
+                check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+                if let Some(ruleset) = &self.ruleset {
+                    check(libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0) as i32)?;
+                }
+                if !self.filter.is_empty() {
+                    let program = libc::sock_fprog {
+                        len: self.filter.len() as u16,
+                        filter: self.filter.as_ptr() as *mut _,
+                    };
+                    check(libc::syscall(
+                        libc::SYS_seccomp,
+                        libc::SECCOMP_SET_MODE_FILTER,
+                        0,
+                        &program as *const libc::sock_fprog,
+                    ) as i32)?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    pub fn landlock_abi() -> Option<u32> {
+        let abi = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<RulesetAttr>(),
+                0usize,
+                LANDLOCK_CREATE_RULESET_VERSION,
+            )
+        };
+        (abi > 0).then_some(abi as u32)
+    }
+
+    pub fn seccomp_available() -> bool {
+        cfg!(any(target_arch = "x86_64", target_arch = "aarch64")) && unsafe { libc::prctl(libc::PR_GET_SECCOMP) } >= 0
+    }
+
+    /// Containers and hardened kernels often refuse unprivileged user
+    /// namespaces, so try the real setup in a throwaway child.
+    pub fn namespaces_available() -> bool {
+        let (uid_map, gid_map) = id_maps();
+        let mut probe = std::process::Command::new("sh");
+        probe.arg("-c").arg("true");
+        unsafe {
+            probe.pre_exec(move || enter_namespaces(&uid_map, &gid_map));
+        }
+        probe.status().is_ok_and(|status| status.success())
+    }
+
+    /// Maps our own IDs into the new user namespace, so files keep their
+    /// owners.
+    fn id_maps() -> (Vec<u8>, Vec<u8>) {
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        (format!("{uid} {uid} 1").into_bytes(), format!("{gid} {gid} 1").into_bytes())
+    }
+
+    unsafe fn enter_namespaces(uid_map: &[u8], gid_map: &[u8]) -> io::Result<()> {
+        check(libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET))?;
+        write_proc(c"/proc/self/setgroups", b"deny")?;
+        write_proc(c"/proc/self/uid_map", uid_map)?;
+        write_proc(c"/proc/self/gid_map", gid_map)?;
+        loopback_up()
+    }
+
+    unsafe fn write_proc(path: &std::ffi::CStr, contents: &[u8]) -> io::Result<()> {
+        let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+        check(fd)?;
+        let written = libc::write(fd, contents.as_ptr().cast(), contents.len());
+        libc::close(fd);
+        if written != contents.len() as isize {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// A new network namespace starts with loopback down; bring it up so
+    /// tests can still talk to local servers.
+    unsafe fn loopback_up() -> io::Result<()> {
+        let socket = libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+        check(socket)?;
+        let mut request: libc::ifreq = std::mem::zeroed();
+        for (dst, src) in request.ifr_name.iter_mut().zip(b"lo") {
+            *dst = *src as libc::c_char;
+        }
+        let mut result = libc::ioctl(socket, libc::SIOCGIFFLAGS as _, &mut request);
+        if result == 0 {
+            request.ifr_ifru.ifru_flags |= (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short;
+            result = libc::ioctl(socket, libc::SIOCSIFFLAGS as _, &request);
+        }
+        libc::close(socket);
+        check(result)
+    }
+
+    fn check(result: libc::c_int) -> io::Result<()> {
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// A Landlock ruleset that handles every kind of write and allows them
+    /// only under `writable` and on a few device files.
+    fn ruleset(abi: u32, writable: &[impl AsRef<Path>]) -> io::Result<OwnedFd> {
+        let mut handled = ACCESS_FS_WRITE_FILE | ACCESS_FS_REMOVE_DIR | ACCESS_FS_REMOVE_FILE | ACCESS_FS_MAKE_ANY;
+        if abi >= 2 {
+            handled |= ACCESS_FS_REFER;
+        }
+        if abi >= 3 {
+            handled |= ACCESS_FS_TRUNCATE;
+        }
+        let attr = RulesetAttr {
+            handled_access_fs: handled,
+        };
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const RulesetAttr,
+                std::mem::size_of::<RulesetAttr>(),
+                0,
+            )
+        };
+        check(fd as i32)?;
+        let ruleset = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+
+        for dir in writable {
+            add_rule(&ruleset, dir.as_ref(), handled)?;
+        }
+        // Rules on files may only grant file rights.
+        let file_access = handled & (ACCESS_FS_WRITE_FILE | ACCESS_FS_TRUNCATE);
+        for device in WRITABLE_DEVICES.iter().map(Path::new).filter(|path| path.exists()) {
+            add_rule(&ruleset, device, file_access)?;
+        }
+        Ok(ruleset)
+    }
+
+    fn add_rule(ruleset: &OwnedFd, path: &Path, access: u64) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_PATH | libc::O_CLOEXEC)
+            .open(path)?;
+        let attr = PathBeneathAttr {
+            allowed_access: access,
+            parent_fd: file.as_raw_fd(),
+        };
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset.as_raw_fd(),
+                LANDLOCK_RULE_PATH_BENEATH,
+                &attr as *const PathBeneathAttr,
+                0,
+            )
+        };
+        check(result as i32)
+    }
+
+    /// A seccomp program that refuses `DENIED_SYSCALLS` with `EPERM` and,
+    /// when `block_inet` is set, internet and raw sockets. Anything built
+    /// for another architecture is killed, since its numbers differ.
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    fn filter(block_inet: bool) -> Vec<libc::sock_filter> {
+        use libc::{sock_filter, BPF_ABS, BPF_JEQ, BPF_JGE, BPF_JMP, BPF_K, BPF_LD, BPF_RET, BPF_W};
+
+        const NR: u32 = 0;
+        const ARCH: u32 = 4;
+        const ARG0: u32 = 16;
+        let load = |k| sock_filter { code: (BPF_LD | BPF_W | BPF_ABS) as u16, jt: 0, jf: 0, k };
+        let ret = |k| sock_filter { code: (BPF_RET | BPF_K) as u16, jt: 0, jf: 0, k };
+        let jeq = |k, jt, jf| sock_filter { code: (BPF_JMP | BPF_JEQ | BPF_K) as u16, jt, jf, k };
+        let deny = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+        let allow = libc::SECCOMP_RET_ALLOW;
+
+        let mut program = vec![
+            load(ARCH),
+            jeq(AUDIT_ARCH, 1, 0),
+            ret(libc::SECCOMP_RET_KILL_PROCESS),
+            load(NR),
+        ];
+        // x32 system calls share the architecture but set this bit.
+        #[cfg(target_arch = "x86_64")]
+        program.extend([
+            sock_filter { code: (BPF_JMP | BPF_JGE | BPF_K) as u16, jt: 0, jf: 1, k: 0x4000_0000 },
+            ret(deny),
+        ]);
+        for &nr in DENIED_SYSCALLS {
+            program.extend([jeq(nr as u32, 0, 1), ret(deny)]);
+        }
+        if block_inet {
+            program.extend([
+                jeq(libc::SYS_socket as u32, 0, 6),
+                load(ARG0),
+                jeq(libc::AF_INET as u32, 3, 0),
+                jeq(libc::AF_INET6 as u32, 2, 0),
+                jeq(libc::AF_PACKET as u32, 1, 0),
+                ret(allow),
+                ret(deny),
+            ]);
+        }
+        program.push(ret(allow));
+        program
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    fn filter(_block_inet: bool) -> Vec<libc::sock_filter> {
+        Vec::new()
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::tools;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    /// A fresh directory under `base`.
+    fn scratch(base: &Path) -> PathBuf {
+        let dir = base.join(format!(
+            "forge-sandbox-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    fn sandbox(workspace: &Path) -> Sandbox {
+        let policy = SandboxPolicy {
+            enabled: true,
+            ..SandboxPolicy::default()
+        };
+        Sandbox::new(&policy, &Workspace::new(workspace).unwrap()).unwrap()
+    }
+
+    async fn run(command: &str, cwd: &Path, sandbox: Option<&Sandbox>) -> Option<i32> {
+        tools::bash(command, Some(cwd), sandbox, |_, _| {}).await.unwrap().exit_code
+    }
+
+    #[tokio::test]
+    async fn writes_outside_the_workspace_fail() {
+        let workspace = scratch(&std::env::temp_dir());
+        let sandbox = sandbox(&workspace);
+        if sandbox.support().landlock_abi.is_none() {
+            eprintln!("skipping: Landlock is unavailable");
+            return;
+        }
+        // Temp dirs are writable, so the outside directory lives in target/.
+        let outside = scratch(&Path::new(env!("CARGO_MANIFEST_DIR")).join("target"));
+        let target = outside.join("escaped");
+
+        let inside = run("echo ok > written", &workspace, Some(&sandbox)).await;
+        assert_eq!(inside, Some(0));
+        assert!(workspace.join("written").exists());
+
+        let command = format!("echo no > '{}'", target.display());
+        assert_ne!(run(&command, &workspace, Some(&sandbox)).await, Some(0));
+        assert!(!target.exists());
+
+        assert_eq!(run(&command, &workspace, None).await, Some(0));
+        assert!(target.exists());
+        let _ = std::fs::remove_dir_all(outside);
+    }
+
+    #[tokio::test]
+    async fn connecting_fails_without_network() {
+        let workspace = scratch(&std::env::temp_dir());
+        let sandbox = sandbox(&workspace);
+        if !sandbox.support().namespaces && !sandbox.support().seccomp {
+            eprintln!("skipping: neither namespaces nor seccomp are available");
+            return;
+        }
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let command = format!("bash -c 'exec 3<>/dev/tcp/127.0.0.1/{}'", port);
+
+        assert_ne!(run(&command, &workspace, Some(&sandbox)).await, Some(0));
+        let allowed = sandbox.clone().with_network();
+        assert_eq!(run(&command, &workspace, Some(&allowed)).await, Some(0));
+        assert_eq!(run(&command, &workspace, None).await, Some(0));
+    }
+
+    #[test]
+    fn gaps_name_the_missing_kernel_features() {
+        let workspace = scratch(&std::env::temp_dir());
+        let mut sandbox = sandbox(&workspace);
+
+        sandbox.support = Support::default();
+        let gaps = sandbox.gaps();
+        assert_eq!(gaps.len(), 3, "{:?}", gaps);
+        assert!(gaps[0].contains("Landlock is unavailable"));
+        assert!(gaps[1].contains("commands keep network access"));
+        assert!(gaps[2].contains("seccomp is unavailable"));
+
+        sandbox.support.seccomp = true;
+        let gaps = sandbox.gaps();
+        assert_eq!(gaps.len(), 2, "{:?}", gaps);
+        assert!(gaps[1].contains("blocked with seccomp"));
+
+        // Namespaces don't matter once the network is allowed.
+        let gaps = sandbox.clone().with_network().gaps();
+        assert_eq!(gaps.len(), 1, "{:?}", gaps);
+
+        sandbox.support = Support {
+            namespaces: true,
+            landlock_abi: Some(1),
+            seccomp: true,
+        };
+        assert!(sandbox.gaps().is_empty());
+    }
+}
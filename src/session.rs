@@ -0,0 +1,72 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::agent::Message;
+use crate::types::TodoItem;
+use crate::usage::Usage;
+
+/// Everything needed to pick a conversation back up: the message history
+/// and the task list.
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub messages: Vec<Message>,
+    pub todos: Vec<TodoItem>,
+    /// Token totals across every API call in the session.
+    #[serde(default)]
+    pub usage: Usage,
+    #[serde(default)]
+    pub api_calls: u32,
+    /// USD, computed from the price table when the session was saved.
+    #[serde(default)]
+    pub cost_usd: Option<f64>,
+}
+
+impl Session {
+    pub fn new_id() -> String {
+        format!("{}-{}", now(), std::process::id())
+    }
+
+    /// `$XDG_DATA_HOME/forge/sessions`, or `~/.local/share/forge/sessions`.
+    pub fn dir() -> PathBuf {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+            .unwrap_or_else(std::env::temp_dir)
+            .join("forge")
+            .join("sessions")
+    }
+
+    pub fn path(id: &str) -> PathBuf {
+        Self::dir().join(format!("{}.json", id))
+    }
+
+    pub fn save(&mut self) -> Result<PathBuf> {
+        self.updated_at = now();
+        fs::create_dir_all(Self::dir())?;
+        let path = Self::path(&self.id);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(path)
+    }
+
+    pub fn load(id: &str) -> Result<Self> {
+        let path = Self::path(id);
+        let content = fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("Failed to read session {}: {}", path.display(), e))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
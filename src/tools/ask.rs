@@ -1,22 +1,55 @@
 use anyhow::Result;
-use inquire::{Select, MultiSelect, Text};
-use crate::types::AskResult;
+use inquire::{InquireError, Select, MultiSelect, Text};
+use crate::types::{AskResult, ToolDefinition};
+use serde_json::json;
 
+/// Asks the user a question. When nobody is at the terminal the question is
+/// not shown; the configured `default_answer` (if any) is handed back in
+/// `AskResult::Unattended` instead of blocking on input.
 pub fn ask(
     question: &str,
     options: Option<Vec<String>>,
     multi: bool,
+    interactive: bool,
+    default_answer: Option<&str>,
 ) -> Result<AskResult> {
-    if let Some(choices) = options {
+    let unattended = || AskResult::Unattended(default_answer.map(str::to_string));
+
+    if !interactive {
+        return Ok(unattended());
+    }
+
+    let result = if let Some(choices) = options {
         if multi {
-            let selected = MultiSelect::new(question, choices).prompt()?;
-            Ok(AskResult::Multi(selected))
+            MultiSelect::new(question, choices).prompt().map(AskResult::Multi)
         } else {
-            let selected = Select::new(question, choices).prompt()?;
-            Ok(AskResult::Single(selected))
+            Select::new(question, choices).prompt().map(AskResult::Single)
         }
     } else {
-        let answer = Text::new(question).prompt()?;
-        Ok(AskResult::Text(answer))
+        Text::new(question).prompt().map(AskResult::Text)
+    };
+
+    match result {
+        Ok(answer) => Ok(answer),
+        Err(InquireError::NotTTY) => Ok(unattended()),
+        Err(InquireError::OperationCanceled) => Ok(AskResult::Declined),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "ask".to_string(),
+        description: "Ask the user a question when the request is ambiguous. Use single or multi with options for multiple choice, or text for a free-form answer.".to_string(),
+        input_schema: json!({
+            "type": "object",
+            "properties": {
+                "question": { "type": "string" },
+                "mode": { "type": "string", "enum": ["single", "multi", "text"] },
+                "options": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["question", "mode"]
+        }),
+        read_only: false,
     }
 }
@@ -1,20 +1,71 @@
 use anyhow::Result;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::Command;
+use std::path::Path;
 use std::process::Stdio;
+use serde::Serialize;
+use serde_json::json;
+use crate::sandbox::Sandbox;
+use crate::types::ToolDefinition;
 
 pub struct BashOutput {
     pub output: String,
     pub exit_code: Option<i32>,
 }
 
-pub async fn bash(command: &str) -> Result<BashOutput> {
-    let mut child = Command::new("sh")
-        .arg("-c")
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Kills the command's whole process group if the call is dropped before
+/// the command exits, e.g. when the user interrupts the turn. Clear `pgid`
+/// once the command has exited normally.
+pub(crate) struct ProcessGroup {
+    pub(crate) pgid: Option<u32>,
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(pgid) = self.pgid {
+            unsafe {
+                libc::killpg(pgid as libc::pid_t, libc::SIGKILL);
+            }
+        }
+    }
+}
+
+/// Runs `command` in `cwd` (or Forge's own directory), inside `sandbox` if
+/// given, passing each line of output to `on_line` as it arrives. The
+/// returned output has both streams interleaved in arrival order.
+pub async fn bash(
+    command: &str,
+    cwd: Option<&Path>,
+    sandbox: Option<&Sandbox>,
+    mut on_line: impl FnMut(OutputStream, &str) + Send,
+) -> Result<BashOutput> {
+    let mut cmd = Command::new("sh");
+    if let Some(dir) = cwd {
+        cmd.current_dir(dir);
+    }
+    cmd.arg("-c")
         .arg(command)
+        .stdin(Stdio::null())
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()?;
+        .stderr(Stdio::piped());
+    // Own process group, so terminal Ctrl-C reaches Forge rather than the
+    // command, and cancellation can take down everything it spawned.
+    #[cfg(unix)]
+    cmd.process_group(0);
+    if let Some(sandbox) = sandbox {
+        sandbox.apply(&mut cmd)?;
+    }
+
+    let mut child = cmd.spawn()?;
+    let mut group = ProcessGroup { pgid: child.id() };
 
     let stdout = child.stdout.take().unwrap();
     let stderr = child.stderr.take().unwrap();
@@ -29,7 +80,7 @@ pub async fn bash(command: &str) -> Result<BashOutput> {
             line = stdout_reader.next_line() => {
                 match line? {
                     Some(l) => {
-                        println!("{}", l);
+                        on_line(OutputStream::Stdout, &l);
                         output.push_str(&l);
                         output.push('\n');
                     }
@@ -37,22 +88,41 @@ pub async fn bash(command: &str) -> Result<BashOutput> {
                 }
             }
             line = stderr_reader.next_line() => {
-                match line? {
-                    Some(l) => {
-                        eprintln!("{}", l);
-                        output.push_str(&l);
-                        output.push('\n');
-                    }
-                    None => {}
+                if let Some(l) = line? {
+                    on_line(OutputStream::Stderr, &l);
+                    output.push_str(&l);
+                    output.push('\n');
                 }
             }
         }
     }
 
     let status = child.wait().await?;
+    group.pgid = None;
 
     Ok(BashOutput {
         output,
         exit_code: status.code(),
     })
 }
+
+pub fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "bash".to_string(),
+        description: "Run a shell command with sh -c and return its combined output.".to_string(),
+        input_schema: json!({
+            "type": "object",
+            "properties": {
+                "command": { "type": "string", "description": "Command to run" },
+                "escalate": {
+                    "type": "string",
+                    "enum": ["network", "unsandboxed"],
+                    "description": "Only if the command failed because of the sandbox: ask the user to run it \
+                        with network access, or without the sandbox"
+                }
+            },
+            "required": ["command"]
+        }),
+        read_only: false,
+    }
+}
@@ -2,6 +2,8 @@ use anyhow::Result;
 use similar::TextDiff;
 use std::fs;
 use std::path::Path;
+use serde_json::json;
+use crate::types::ToolDefinition;
 
 pub fn edit(path: &str, search: &str, replace: &str, replace_all: bool) -> Result<String> {
     if !Path::new(path).exists() {
@@ -36,3 +38,21 @@ pub fn edit(path: &str, search: &str, replace: &str, replace_all: bool) -> Resul
 
     Ok(diff_output)
 }
+
+pub fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "edit".to_string(),
+        description: "Replace exact text in a file. Fails if old_string is not found.".to_string(),
+        input_schema: json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "description": "File to edit" },
+                "old_string": { "type": "string", "description": "Exact text to replace" },
+                "new_string": { "type": "string", "description": "Replacement text" },
+                "replace_all": { "type": "boolean", "description": "Replace every occurrence instead of the first" }
+            },
+            "required": ["path", "old_string", "new_string"]
+        }),
+        read_only: false,
+    }
+}
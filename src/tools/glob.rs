@@ -2,6 +2,8 @@ use anyhow::Result;
 use globset::Glob;
 use walkdir::WalkDir;
 use std::time::SystemTime;
+use serde_json::json;
+use crate::types::ToolDefinition;
 
 pub fn glob(pattern: &str, base_path: Option<&str>) -> Result<Vec<String>> {
     let glob = Glob::new(pattern)?.compile_matcher();
@@ -11,7 +13,10 @@ pub fn glob(pattern: &str, base_path: Option<&str>) -> Result<Vec<String>> {
 
     for entry in WalkDir::new(path).follow_links(true) {
         let entry = entry?;
-        if glob.is_match(entry.path()) {
+        // Match relative to the search root, so `src/**/*.rs` works from
+        // any base directory.
+        let relative = entry.path().strip_prefix(path).unwrap_or(entry.path());
+        if glob.is_match(relative) {
             if let Ok(metadata) = entry.metadata() {
                 if let Ok(modified) = metadata.modified() {
                     matches.push((entry.path().display().to_string(), modified));
@@ -20,7 +25,23 @@ pub fn glob(pattern: &str, base_path: Option<&str>) -> Result<Vec<String>> {
         }
     }
 
-    matches.sort_by(|a, b| b.1.cmp(&a.1));
+    matches.sort_by_key(|m| std::cmp::Reverse(m.1));
 
     Ok(matches.into_iter().map(|(path, _)| path).collect())
 }
+
+pub fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "glob".to_string(),
+        description: "Find files matching a glob pattern, newest first.".to_string(),
+        input_schema: json!({
+            "type": "object",
+            "properties": {
+                "pattern": { "type": "string", "description": "Glob pattern, e.g. src/**/*.rs" },
+                "path": { "type": "string", "description": "Directory to search (default: current directory)" }
+            },
+            "required": ["pattern"]
+        }),
+        read_only: true,
+    }
+}
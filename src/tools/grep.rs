@@ -5,7 +5,8 @@ use grep_searcher::Searcher;
 use std::path::Path;
 use walkdir::WalkDir;
 
-use crate::types::GrepMatch;
+use crate::types::{GrepMatch, ToolDefinition};
+use serde_json::json;
 
 pub fn grep(
     pattern: &str,
@@ -57,3 +58,20 @@ fn search_file(
 
     Ok(())
 }
+
+pub fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "grep".to_string(),
+        description: "Search file contents with a regular expression.".to_string(),
+        input_schema: json!({
+            "type": "object",
+            "properties": {
+                "pattern": { "type": "string", "description": "Regular expression" },
+                "path": { "type": "string", "description": "File or directory to search (default: current directory)" },
+                "case_insensitive": { "type": "boolean" }
+            },
+            "required": ["pattern"]
+        }),
+        read_only: true,
+    }
+}
@@ -7,13 +7,49 @@ pub mod grep;
 pub mod websearch;
 pub mod webfetch;
 pub mod ask;
+pub mod todo;
 
 pub use read::read;
 pub use write::write;
 pub use edit::edit;
-pub use bash::{bash, BashOutput};
+pub use bash::{bash, BashOutput, OutputStream};
 pub use glob::glob;
 pub use grep::grep;
 pub use websearch::websearch;
-pub use webfetch::{webfetch, FetchResult};
+pub use webfetch::{webfetch, FetchOptions, FetchResult};
 pub use ask::ask;
+pub use todo::todo;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use serde_json::Value;
+
+use crate::types::ToolDefinition;
+
+/// A tool supplied by code embedding the agent. Read-only tools (per
+/// `definition().read_only`) may run in parallel with other read-only
+/// calls; the rest go through the permission check first.
+pub trait Tool: Send + Sync {
+    fn definition(&self) -> ToolDefinition;
+
+    /// Runs the tool. The returned text, or the error message, is sent back
+    /// to the model as the tool result.
+    fn call(&self, input: Value) -> BoxFuture<'static, Result<String>>;
+}
+
+/// Definitions of every built-in tool, in the order they are offered to the
+/// model.
+pub fn definitions() -> Vec<ToolDefinition> {
+    vec![
+        read::definition(),
+        write::definition(),
+        edit::definition(),
+        bash::definition(),
+        glob::definition(),
+        grep::definition(),
+        websearch::definition(),
+        webfetch::definition(),
+        ask::definition(),
+        todo::definition(),
+    ]
+}
@@ -1,6 +1,8 @@
 use anyhow::Result;
 use std::fs;
 use std::path::Path;
+use serde_json::json;
+use crate::types::ToolDefinition;
 
 pub fn read(path: &str, offset: Option<usize>, limit: Option<usize>) -> Result<String> {
     if !Path::new(path).exists() {
@@ -10,7 +12,7 @@ pub fn read(path: &str, offset: Option<usize>, limit: Option<usize>) -> Result<S
     let content = fs::read_to_string(path)?;
     let lines: Vec<&str> = content.lines().collect();
 
-    let start = offset.unwrap_or(0);
+    let start = offset.unwrap_or(0).min(lines.len());
     let end = limit
         .map(|l| (start + l).min(lines.len()))
         .unwrap_or(lines.len());
@@ -23,3 +25,20 @@ pub fn read(path: &str, offset: Option<usize>, limit: Option<usize>) -> Result<S
 
     Ok(formatted_lines.join("\n"))
 }
+
+pub fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "read".to_string(),
+        description: "Read a file. Returns its lines prefixed with line numbers.".to_string(),
+        input_schema: json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "description": "File to read" },
+                "offset": { "type": "integer", "description": "Line to start from (0-based)" },
+                "limit": { "type": "integer", "description": "Maximum number of lines to return" }
+            },
+            "required": ["path"]
+        }),
+        read_only: true,
+    }
+}
@@ -0,0 +1,42 @@
+use anyhow::Result;
+use crate::types::{TodoItem, TodoStatus, ToolDefinition};
+use serde_json::json;
+
+/// Validates a full replacement task list.
+pub fn todo(todos: Vec<TodoItem>) -> Result<Vec<TodoItem>> {
+    if let Some(empty) = todos.iter().position(|t| t.content.trim().is_empty()) {
+        return Err(anyhow::anyhow!("Task {} has no content", empty + 1));
+    }
+
+    let in_progress = todos.iter().filter(|t| t.status == TodoStatus::InProgress).count();
+    if in_progress > 1 {
+        return Err(anyhow::anyhow!("Only one task can be in_progress at a time, got {}", in_progress));
+    }
+
+    Ok(todos)
+}
+
+pub fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "todo".to_string(),
+        description: "Replace the task list for multi-step work. Send the full list every time; keep exactly one task in_progress while working.".to_string(),
+        input_schema: json!({
+            "type": "object",
+            "properties": {
+                "todos": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "content": { "type": "string" },
+                            "status": { "type": "string", "enum": ["pending", "in_progress", "completed"] }
+                        },
+                        "required": ["content", "status"]
+                    }
+                }
+            },
+            "required": ["todos"]
+        }),
+        read_only: false,
+    }
+}
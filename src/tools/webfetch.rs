@@ -1,25 +1,433 @@
 use anyhow::Result;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use scraper::{ElementRef, Html, Selector};
 use std::time::Duration;
 
+use crate::cache::{CachedResponse, HttpCache, Lookup};
+use crate::egress::EgressPolicy;
+use crate::types::{ToolError, ToolDefinition};
+use serde_json::json;
+
+/// Tags that never carry page content.
+const BOILERPLATE_TAGS: &str =
+    "script, style, noscript, template, nav, footer, aside, form, iframe, svg, button, dialog";
+
+/// Class/id fragments that mark navigation, banners and other chrome.
+const BOILERPLATE_HINTS: &[&str] = &[
+    "cookie", "consent", "banner", "navbar", "menu", "breadcrumb", "footer", "sidebar",
+    "share", "social", "subscribe", "newsletter", "advert", "promo", "popup", "modal",
+    "related", "comment",
+];
+
+pub struct FetchOptions {
+    /// Stop downloading after this many bytes.
+    pub max_bytes: usize,
+    /// Character offset into the converted content.
+    pub offset: usize,
+    /// Maximum characters returned per page.
+    pub max_chars: usize,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        Self {
+            max_bytes: 5 * 1024 * 1024,
+            offset: 0,
+            max_chars: 20_000,
+        }
+    }
+}
+
 pub struct FetchResult {
     pub url: String,
+    pub status: u16,
+    pub content_type: String,
+    pub title: Option<String>,
     pub content: String,
+    /// Total characters of converted content, across all pages.
+    pub total_chars: usize,
+    /// Offset to pass back in to read the next page, if there is one.
+    pub next_offset: Option<usize>,
+    /// The download was cut off at `max_bytes`.
+    pub truncated: bool,
 }
 
-pub async fn webfetch(url: &str) -> Result<FetchResult> {
-    let client = reqwest::Client::builder()
-        .user_agent("Forge/1.0")
-        .timeout(Duration::from_secs(30))
-        .build()?;
+pub async fn webfetch(
+    url: &str,
+    options: &FetchOptions,
+    cache: Option<&HttpCache>,
+    policy: &EgressPolicy,
+) -> Result<FetchResult> {
+    // Checked up front so a policy change also applies to cached pages.
+    policy.check_url(url).map_err(ToolError::from)?;
 
-    let response = client.get(url).send().await?;
+    let response = match cache {
+        Some(cache) => fetch_cached(url, options, cache, policy).await?,
+        None => download(url, options, HeaderMap::new(), policy).await?.0,
+    };
+
+    // Parsing a large page or PDF is CPU-bound; keep it off the async workers.
+    let CachedResponse { url, status, content_type, truncated, body, .. } = response;
+    let (content_type, (title, content)) =
+        tokio::task::spawn_blocking(move || convert(&content_type, &body).map(|converted| (content_type, converted)))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to convert {}: {}", url, e))??;
+
+    Ok(paginate(FetchResult {
+        url,
+        status,
+        content_type,
+        title,
+        content,
+        total_chars: 0,
+        next_offset: None,
+        truncated,
+    }, options))
+}
+
+async fn fetch_cached(
+    url: &str,
+    options: &FetchOptions,
+    cache: &HttpCache,
+    policy: &EgressPolicy,
+) -> Result<CachedResponse> {
+    let stale = match cache.lookup(url)? {
+        Lookup::Fresh(entry) => return Ok(entry),
+        Lookup::Stale(entry) => Some(entry),
+        Lookup::Miss => None,
+    };
+
+    let conditional = stale.as_ref().map(HttpCache::conditional_headers).unwrap_or_default();
+    let (response, headers) = download(url, options, conditional, policy).await?;
+
+    match stale {
+        Some(entry) if response.status == StatusCode::NOT_MODIFIED.as_u16() => {
+            cache.refresh(url, entry, &headers)
+        }
+        _ if response.status == StatusCode::OK.as_u16() => cache.store(url, response, &headers),
+        _ => Ok(response),
+    }
+}
+
+async fn download(
+    url: &str,
+    options: &FetchOptions,
+    headers: HeaderMap,
+    policy: &EgressPolicy,
+) -> Result<(CachedResponse, HeaderMap)> {
+    let mut response = policy.get(url, "Forge/1.0", Duration::from_secs(30), headers).await?;
     let final_url = response.url().to_string();
-    let html = response.text().await?;
+    let status = response.status().as_u16();
+    let headers = response.headers().clone();
+    let content_type = headers
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or(v).trim().to_lowercase())
+        .unwrap_or_default();
 
-    let markdown = html2md::parse_html(&html);
+    let mut body = Vec::new();
+    let mut truncated = false;
+    while let Some(chunk) = response.chunk().await? {
+        let remaining = options.max_bytes - body.len();
+        if chunk.len() > remaining {
+            body.extend_from_slice(&chunk[..remaining]);
+            truncated = true;
+            break;
+        }
+        body.extend_from_slice(&chunk);
+    }
 
-    Ok(FetchResult {
+    let response = CachedResponse {
         url: final_url,
-        content: markdown,
-    })
+        status,
+        content_type,
+        etag: None,
+        last_modified: None,
+        stored_at: 0,
+        max_age: None,
+        no_cache: false,
+        truncated,
+        body,
+    };
+    Ok((response, headers))
+}
+
+fn convert(content_type: &str, body: &[u8]) -> Result<(Option<String>, String)> {
+    let text = || String::from_utf8_lossy(body).into_owned();
+
+    match content_type {
+        "text/html" | "application/xhtml+xml" | "" => Ok(extract_main_content(&text())),
+        "application/pdf" => {
+            // pdf_extract panics on some malformed files; a bad download
+            // must fail the call, not the agent.
+            let content = std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(body))
+                .map_err(|_| anyhow::anyhow!("Failed to extract PDF text: the file is malformed"))?
+                .map_err(|e| anyhow::anyhow!("Failed to extract PDF text: {}", e))?;
+            Ok((None, content))
+        }
+        ct if ct == "application/json" || ct.ends_with("+json") => {
+            let content = serde_json::from_slice::<serde_json::Value>(body)
+                .ok()
+                .and_then(|v| serde_json::to_string_pretty(&v).ok())
+                .unwrap_or_else(text);
+            Ok((None, content))
+        }
+        ct if ct.starts_with("text/") || ct.ends_with("+xml") || ct == "application/xml" => {
+            Ok((None, text()))
+        }
+        ct => Err(anyhow::anyhow!("Unsupported content type: {}", ct)),
+    }
+}
+
+/// Picks the element most likely to hold the article body, strips page
+/// chrome out of it, and converts what is left to markdown.
+fn extract_main_content(html: &str) -> (Option<String>, String) {
+    let mut document = Html::parse_document(html);
+
+    let title = select_first(&document, "meta[property='og:title']")
+        .and_then(|el| el.value().attr("content").map(str::to_string))
+        .or_else(|| select_first(&document, "title").map(|el| el.text().collect::<String>()))
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty());
+
+    let boilerplate = Selector::parse(BOILERPLATE_TAGS).unwrap();
+    let any = Selector::parse("*").unwrap();
+    let mut removals: Vec<_> = document.select(&boilerplate).map(|el| el.id()).collect();
+    removals.extend(document.select(&any).filter(|el| is_boilerplate(el)).map(|el| el.id()));
+    // A header inside the content holds its title and byline; only the
+    // page banner goes.
+    let headers = Selector::parse("header").unwrap();
+    removals.extend(document.select(&headers).filter(|el| !in_content(el)).map(|el| el.id()));
+    for id in removals {
+        if let Some(mut node) = document.tree.get_mut(id) {
+            node.detach();
+        }
+    }
+
+    let root = ["article", "main", "[role='main']"]
+        .iter()
+        .find_map(|s| select_first(&document, s))
+        .or_else(|| best_scoring_block(&document))
+        .or_else(|| select_first(&document, "body"));
+
+    let markdown = match root {
+        Some(el) => html2md::parse_html(&el.html()),
+        None => html2md::parse_html(html),
+    };
+
+    (title, collapse_blank_lines(&markdown))
+}
+
+fn select_first<'a>(document: &'a Html, selector: &str) -> Option<ElementRef<'a>> {
+    let selector = Selector::parse(selector).ok()?;
+    document.select(&selector).next()
+}
+
+fn is_boilerplate(el: &ElementRef) -> bool {
+    let value = el.value();
+    if matches!(value.name(), "html" | "body" | "main" | "article") {
+        return false;
+    }
+    let markers = format!(
+        "{} {}",
+        value.attr("class").unwrap_or(""),
+        value.attr("id").unwrap_or("")
+    )
+    .to_lowercase();
+    BOILERPLATE_HINTS.iter().any(|hint| markers.contains(hint))
+}
+
+fn in_content(el: &ElementRef) -> bool {
+    el.ancestors()
+        .filter_map(ElementRef::wrap)
+        .any(|ancestor| matches!(ancestor.value().name(), "main" | "article"))
+}
+
+/// Scores block containers by the amount of paragraph text they hold
+/// directly, discounting text that sits inside links.
+fn best_scoring_block(document: &Html) -> Option<ElementRef<'_>> {
+    let blocks = Selector::parse("div, section, td").unwrap();
+    let paragraphs = Selector::parse("p").unwrap();
+    let links = Selector::parse("a").unwrap();
+
+    document
+        .select(&blocks)
+        .map(|block| {
+            let text: usize = block
+                .select(&paragraphs)
+                .map(|p| p.text().map(str::len).sum::<usize>())
+                .sum();
+            let link_text: usize = block
+                .select(&links)
+                .map(|a| a.text().map(str::len).sum::<usize>())
+                .sum();
+            (block, text.saturating_sub(link_text))
+        })
+        .filter(|(_, score)| *score > 0)
+        // Ties go to the innermost block, which has the least surrounding chrome.
+        .fold(None, |best: Option<(ElementRef, usize)>, candidate| match best {
+            Some(b) if b.1 > candidate.1 => Some(b),
+            _ => Some(candidate),
+        })
+        .map(|(block, _)| block)
+}
+
+fn collapse_blank_lines(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut blank_run = 0;
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            blank_run += 1;
+            if blank_run > 1 {
+                continue;
+            }
+        } else {
+            blank_run = 0;
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out.trim().to_string()
+}
+
+fn paginate(mut result: FetchResult, options: &FetchOptions) -> FetchResult {
+    let total_chars = result.content.chars().count();
+    let start = options.offset.min(total_chars);
+    let end = (start + options.max_chars).min(total_chars);
+
+    result.content = result.content.chars().skip(start).take(end - start).collect();
+    result.total_chars = total_chars;
+    result.next_offset = (end < total_chars).then_some(end);
+    result
+}
+
+pub fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "webfetch".to_string(),
+        description: "Fetch a URL and return its main content as markdown. Long pages are paginated; pass the returned offset to read the next page.".to_string(),
+        input_schema: json!({
+            "type": "object",
+            "properties": {
+                "url": { "type": "string" },
+                "offset": { "type": "integer", "description": "Character offset to continue from" }
+            },
+            "required": ["url"]
+        }),
+        read_only: true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(content: &str, offset: usize, max_chars: usize) -> FetchResult {
+        let result = FetchResult {
+            url: String::new(),
+            status: 200,
+            content_type: "text/plain".to_string(),
+            title: None,
+            content: content.to_string(),
+            total_chars: 0,
+            next_offset: None,
+            truncated: false,
+        };
+        let options = FetchOptions {
+            offset,
+            max_chars,
+            ..FetchOptions::default()
+        };
+        paginate(result, &options)
+    }
+
+    #[test]
+    fn keeps_the_article_header_but_not_the_page_banner() {
+        let html = r#"<html><body>
+              <header><a href="/">Site Name</a> Sign in</header>
+              <main><article>
+                <header><h1>Article Title</h1><p class="byline">By A. Writer</p></header>
+                <p>Body text of the article.</p>
+              </article></main>
+            </body></html>"#;
+        let (_, content) = extract_main_content(html);
+        assert!(content.contains("Article Title"), "{:?}", content);
+        assert!(content.contains("By A. Writer"), "{:?}", content);
+        assert!(content.contains("Body text of the article."));
+        assert!(!content.contains("Site Name"), "{:?}", content);
+        assert!(!content.contains("Sign in"), "{:?}", content);
+    }
+
+    #[test]
+    fn extracts_article_without_chrome() {
+        let html = r#"<html><head><title>Fallback</title>
+            <meta property="og:title" content="The Real Title"></head>
+            <body>
+              <nav><a href="/">Home</a></nav>
+              <div class="cookie-banner">We use cookies</div>
+              <article><h1>Heading</h1><p>Body text of the article.</p>
+                <div class="share-buttons">Share this</div></article>
+              <footer>Copyright</footer>
+            </body></html>"#;
+        let (title, content) = extract_main_content(html);
+        assert_eq!(title.as_deref(), Some("The Real Title"));
+        assert!(content.contains("Heading"));
+        assert!(content.contains("Body text of the article."));
+        for chrome in ["Home", "cookies", "Share this", "Copyright"] {
+            assert!(!content.contains(chrome), "{} left in {:?}", chrome, content);
+        }
+    }
+
+    #[test]
+    fn falls_back_to_densest_block() {
+        let html = r#"<html><head><title> Plain </title></head><body>
+            <div><a href="/a">Link one</a> <a href="/b">Link two</a></div>
+            <div><p>First paragraph of real text.</p><p>Second paragraph.</p></div>
+            </body></html>"#;
+        let (title, content) = extract_main_content(html);
+        assert_eq!(title.as_deref(), Some("Plain"));
+        assert!(content.contains("First paragraph of real text."));
+        assert!(!content.contains("Link one"));
+    }
+
+    #[test]
+    fn paginates_by_characters() {
+        let first = page("abcdefghij", 0, 4);
+        assert_eq!(first.content, "abcd");
+        assert_eq!(first.total_chars, 10);
+        assert_eq!(first.next_offset, Some(4));
+
+        let last = page("abcdefghij", 8, 4);
+        assert_eq!(last.content, "ij");
+        assert_eq!(last.next_offset, None);
+
+        let past_end = page("abc", 10, 4);
+        assert_eq!(past_end.content, "");
+        assert_eq!(past_end.next_offset, None);
+    }
+
+    #[test]
+    fn paginates_multi_byte_text_on_char_boundaries() {
+        let text = "héllo wörld 日本語テキスト 🦀🦀";
+        let mut offset = Some(0);
+        let mut joined = String::new();
+        while let Some(start) = offset {
+            let result = page(text, start, 3);
+            assert!(result.content.chars().count() <= 3);
+            joined.push_str(&result.content);
+            offset = result.next_offset;
+        }
+        assert_eq!(joined, text);
+
+        let crab = page(text, text.chars().count() - 2, 1);
+        assert_eq!(crab.content, "🦀");
+        assert_eq!(crab.next_offset, Some(text.chars().count() - 1));
+    }
+
+    #[test]
+    fn malformed_pdf_is_an_error() {
+        for body in [&b""[..], b"%PDF-1.7\n", b"%PDF-1.4\n1 0 obj << /Type /Catalog /Pages 9 0 R >>\ntrailer << /Root 1 0 R >>"] {
+            assert!(convert("application/pdf", body).is_err());
+        }
+    }
 }
@@ -1,18 +1,54 @@
 use anyhow::Result;
-use crate::types::SearchResult;
+use reqwest::header::HeaderMap;
+use std::time::Duration;
+use crate::cache::{CachedResponse, HttpCache, Lookup};
+use crate::egress::EgressPolicy;
+use crate::types::{SearchResult, ToolDefinition};
+use serde_json::json;
 
-pub async fn websearch(query: &str) -> Result<Vec<SearchResult>> {
+pub async fn websearch(
+    query: &str,
+    cache: Option<&HttpCache>,
+    policy: &EgressPolicy,
+) -> Result<Vec<SearchResult>> {
     let url = format!(
         "https://html.duckduckgo.com/html/?q={}",
         urlencoding::encode(query)
     );
 
-    let client = reqwest::Client::builder()
-        .user_agent("Mozilla/5.0")
-        .build()?;
+    let key = format!("websearch:{}", query);
+    let cached = match cache.map(|c| c.lookup(&key)).transpose()? {
+        Some(Lookup::Fresh(entry)) => Some(entry),
+        _ => None,
+    };
 
-    let response = client.get(&url).send().await?;
-    let html = response.text().await?;
+    let html = match cached {
+        Some(entry) => String::from_utf8_lossy(&entry.body).into_owned(),
+        None => {
+            let response = policy
+                .get(&url, "Mozilla/5.0", Duration::from_secs(30), HeaderMap::new())
+                .await?;
+            let status = response.status().as_u16();
+            let headers = response.headers().clone();
+            let html = response.text().await?;
+
+            if let Some(cache) = cache.filter(|_| status == 200) {
+                cache.store(&key, CachedResponse {
+                    url: url.clone(),
+                    status,
+                    content_type: "text/html".to_string(),
+                    etag: None,
+                    last_modified: None,
+                    stored_at: 0,
+                    max_age: None,
+                    no_cache: false,
+                    truncated: false,
+                    body: html.clone().into_bytes(),
+                }, &headers)?;
+            }
+            html
+        }
+    };
 
     let mut results = Vec::new();
 
@@ -39,3 +75,18 @@ pub async fn websearch(query: &str) -> Result<Vec<SearchResult>> {
 
     Ok(results)
 }
+
+pub fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "websearch".to_string(),
+        description: "Search the web.".to_string(),
+        input_schema: json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string" }
+            },
+            "required": ["query"]
+        }),
+        read_only: true,
+    }
+}
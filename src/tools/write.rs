@@ -1,6 +1,8 @@
 use anyhow::Result;
 use std::fs;
 use std::path::Path;
+use serde_json::json;
+use crate::types::ToolDefinition;
 
 pub fn write(path: &str, content: &str) -> Result<()> {
     if let Some(parent) = Path::new(path).parent() {
@@ -12,3 +14,19 @@ pub fn write(path: &str, content: &str) -> Result<()> {
     fs::write(path, content)?;
     Ok(())
 }
+
+pub fn definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "write".to_string(),
+        description: "Create or overwrite a file, creating parent directories as needed.".to_string(),
+        input_schema: json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "description": "File to write" },
+                "content": { "type": "string", "description": "Full file contents" }
+            },
+            "required": ["path", "content"]
+        }),
+        read_only: false,
+    }
+}
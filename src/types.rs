@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -12,12 +13,158 @@ pub enum ToolError {
     PatternError(String),
 
     #[error("Network error: {0}")]
-    NetworkError(String),
+    NetworkError(#[from] NetworkError),
+
+    #[error("Permission denied: {0} is outside the workspace")]
+    OutsideWorkspace(String),
+
+    #[error("Permission denied: {0} matches redaction.deny_read")]
+    DeniedRead(String),
+
+    /// Refused by the permission mode; the message says why.
+    #[error("{0}")]
+    Denied(String),
+
+    #[error("Permission denied by user: {0}")]
+    Rejected(String),
+
+    /// Refused by a `pre_tool_use` hook; the message is the hook's reason.
+    #[error("Blocked by hook: {0}")]
+    HookBlocked(String),
 
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 }
 
+/// Why a web request was refused or failed. Policy violations are reported
+/// before any connection is made.
+#[derive(Error, Debug)]
+pub enum NetworkError {
+    #[error("invalid URL '{0}'")]
+    InvalidUrl(String),
+
+    #[error("scheme '{0}' is not allowed")]
+    SchemeNotAllowed(String),
+
+    #[error("host '{0}' is denied by network policy")]
+    HostDenied(String),
+
+    #[error("host '{0}' is not in the network allowlist")]
+    HostNotAllowed(String),
+
+    #[error("host '{host}' resolves to blocked address {addr}")]
+    BlockedAddress { host: String, addr: std::net::IpAddr },
+
+    #[error("could not resolve host '{0}'")]
+    Unresolvable(String),
+
+    #[error("stopped after {0} redirects")]
+    TooManyRedirects(usize),
+
+    #[error("offline mode: no cached response for {0}")]
+    CacheMiss(String),
+
+    #[error("{0}")]
+    Request(#[from] reqwest::Error),
+}
+
+/// A failed Anthropic API call, classified from the HTTP status and the
+/// `error.type` in the response body.
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("Rate limited: {message}")]
+    RateLimited {
+        message: String,
+        retry_after: Option<std::time::Duration>,
+    },
+
+    #[error("API overloaded: {message}")]
+    Overloaded {
+        message: String,
+        retry_after: Option<std::time::Duration>,
+    },
+
+    #[error("API server error ({status}): {message}")]
+    Server { status: u16, message: String },
+
+    #[error("Authentication failed ({status}): {message}")]
+    Authentication { status: u16, message: String },
+
+    #[error("Conversation is too long for the model's context window: {message}")]
+    ContextTooLong { message: String },
+
+    #[error("Invalid request: {message}")]
+    InvalidRequest { message: String },
+
+    #[error("API error ({status}): {message}")]
+    Other { status: u16, message: String },
+
+    #[error("Could not reach the API: {0}")]
+    Connection(reqwest::Error),
+
+    #[error("Unexpected API response: {0}")]
+    Decode(String),
+}
+
+impl ApiError {
+    /// Builds the error for a non-success response from its status, headers
+    /// and body (`{"type": "error", "error": {"type": ..., "message": ...}}`).
+    pub fn from_response(status: u16, retry_after: Option<std::time::Duration>, body: &str) -> Self {
+        let parsed: Option<serde_json::Value> = serde_json::from_str(body).ok();
+        let error = parsed.as_ref().and_then(|v| v.get("error"));
+        let kind = error.and_then(|e| e.get("type")).and_then(|t| t.as_str()).unwrap_or("");
+        let message = error
+            .and_then(|e| e.get("message"))
+            .and_then(|m| m.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| body.trim().to_string());
+
+        match (status, kind) {
+            (429, _) | (_, "rate_limit_error") => Self::RateLimited { message, retry_after },
+            (529, _) | (_, "overloaded_error") => Self::Overloaded { message, retry_after },
+            (401 | 403, _) | (_, "authentication_error" | "permission_error") => {
+                Self::Authentication { status, message }
+            }
+            (400 | 413, _) if is_context_overflow(&message) => Self::ContextTooLong { message },
+            (400, _) | (_, "invalid_request_error") => Self::InvalidRequest { message },
+            (500..=599, _) | (_, "api_error") => Self::Server { status, message },
+            _ => Self::Other { status, message },
+        }
+    }
+
+    /// Transient failures that are worth retrying after a delay.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::RateLimited { .. } | Self::Overloaded { .. } | Self::Server { .. } => true,
+            Self::Connection(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+            _ => false,
+        }
+    }
+
+    /// Delay requested by the server through `retry-after`, if any.
+    pub fn retry_after(&self) -> Option<std::time::Duration> {
+        match self {
+            Self::RateLimited { retry_after, .. } | Self::Overloaded { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+/// A `retry-after` header in seconds. The HTTP-date form, and values that
+/// aren't a usable duration, are ignored in favour of the backoff.
+pub(crate) fn parse_retry_after(value: &str) -> Option<std::time::Duration> {
+    let seconds: f64 = value.trim().parse().ok()?;
+    std::time::Duration::try_from_secs_f64(seconds).ok()
+}
+
+fn is_context_overflow(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("prompt is too long")
+        || message.contains("context window")
+        || message.contains("context length")
+        || message.contains("too many tokens")
+}
+
 pub struct GrepMatch {
     pub file: String,
     pub line_num: u64,
@@ -34,4 +181,96 @@ pub enum AskResult {
     Single(String),
     Multi(Vec<String>),
     Text(String),
+    /// The user dismissed the question without answering.
+    Declined,
+    /// No user is available; carries the configured default answer, if any.
+    Unattended(Option<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoStatus {
+    Pending,
+    InProgress,
+    Completed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TodoItem {
+    pub content: String,
+    pub status: TodoStatus,
+}
+
+/// A tool as advertised to the model: name, description and JSON schema
+/// for its input.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub input_schema: serde_json::Value,
+    /// The tool has no side effects, so consecutive calls to read-only
+    /// tools may run concurrently.
+    #[serde(skip)]
+    pub read_only: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn error_body(kind: &str, message: &str) -> String {
+        serde_json::json!({ "type": "error", "error": { "type": kind, "message": message } }).to_string()
+    }
+
+    #[test]
+    fn statuses_map_to_error_kinds() {
+        let retry = Some(Duration::from_secs(3));
+        let error = ApiError::from_response(429, retry, &error_body("rate_limit_error", "slow down"));
+        assert!(matches!(&error, ApiError::RateLimited { message, .. } if message == "slow down"));
+        assert_eq!(error.retry_after(), retry);
+        assert!(error.is_retryable());
+
+        let error = ApiError::from_response(529, retry, &error_body("overloaded_error", "busy"));
+        assert!(matches!(error, ApiError::Overloaded { .. }));
+        assert_eq!(error.retry_after(), retry);
+
+        let error = ApiError::from_response(503, retry, "upstream unavailable");
+        assert!(matches!(&error, ApiError::Server { status: 503, message } if message == "upstream unavailable"));
+        assert_eq!(error.retry_after(), None);
+        assert!(error.is_retryable());
+
+        for status in [401, 403] {
+            let error = ApiError::from_response(status, None, &error_body("authentication_error", "bad key"));
+            assert!(matches!(error, ApiError::Authentication { .. }));
+            assert!(!error.is_retryable());
+        }
+
+        let error = ApiError::from_response(400, None, &error_body("invalid_request_error", "prompt is too long: 250000 tokens"));
+        assert!(matches!(error, ApiError::ContextTooLong { .. }));
+        let error = ApiError::from_response(400, None, &error_body("invalid_request_error", "thinking is not supported"));
+        assert!(matches!(error, ApiError::InvalidRequest { .. }));
+        assert!(!error.is_retryable());
+
+        let error = ApiError::from_response(418, None, "teapot");
+        assert!(matches!(error, ApiError::Other { status: 418, .. }));
+    }
+
+    #[test]
+    fn the_error_type_wins_over_an_unusual_status() {
+        let error = ApiError::from_response(200, None, &error_body("overloaded_error", "busy"));
+        assert!(matches!(error, ApiError::Overloaded { .. }));
+        let error = ApiError::from_response(200, None, &error_body("api_error", "oops"));
+        assert!(matches!(error, ApiError::Server { status: 200, .. }));
+    }
+
+    #[test]
+    fn retry_after_accepts_only_seconds() {
+        assert_eq!(parse_retry_after("7"), Some(Duration::from_secs(7)));
+        assert_eq!(parse_retry_after(" 1.5 "), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_retry_after("0"), Some(Duration::ZERO));
+        for invalid in ["-1", "NaN", "inf", "", "Wed, 21 Oct 2015 07:28:00 GMT"] {
+            assert_eq!(parse_retry_after(invalid), None, "{:?}", invalid);
+        }
+    }
 }
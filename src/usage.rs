@@ -0,0 +1,207 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::AddAssign;
+
+/// Token counts as reported in the API response's `usage` field.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Usage {
+    #[serde(default)]
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub cache_creation_input_tokens: u64,
+    #[serde(default)]
+    pub cache_read_input_tokens: u64,
+}
+
+impl Usage {
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens + self.cache_creation_input_tokens + self.cache_read_input_tokens
+    }
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_input_tokens += other.cache_creation_input_tokens;
+        self.cache_read_input_tokens += other.cache_read_input_tokens;
+    }
+}
+
+/// USD per million tokens.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    pub cache_write: f64,
+    pub cache_read: f64,
+}
+
+impl ModelPrice {
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        (usage.input_tokens as f64 * self.input
+            + usage.output_tokens as f64 * self.output
+            + usage.cache_creation_input_tokens as f64 * self.cache_write
+            + usage.cache_read_input_tokens as f64 * self.cache_read)
+            / 1_000_000.0
+    }
+}
+
+/// Prices keyed by model ID or ID prefix, so `claude-sonnet-4-5` also
+/// covers dated snapshots like `claude-sonnet-4-5-20250929`. Entries from
+/// the `[pricing]` config table are merged over the built-in ones when the
+/// config layers are merged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PriceTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl Default for PriceTable {
+    fn default() -> Self {
+        let price = |input: f64, output: f64| ModelPrice {
+            input,
+            output,
+            cache_write: input * 1.25,
+            cache_read: input * 0.1,
+        };
+        // Lookup takes the longest matching prefix, so newer models listed
+        // here win over the family entries below them.
+        let prices = [
+            ("claude-opus-4-6", price(5.0, 25.0)),
+            ("claude-opus-4-5", price(5.0, 25.0)),
+            ("claude-opus-4", price(15.0, 75.0)),
+            ("claude-sonnet-4", price(3.0, 15.0)),
+            ("claude-3-7-sonnet", price(3.0, 15.0)),
+            ("claude-haiku-4-5", price(1.0, 5.0)),
+            ("claude-3-5-haiku", price(0.8, 4.0)),
+            ("claude-3-opus", price(15.0, 75.0)),
+            ("claude-3-haiku", price(0.25, 1.25)),
+        ];
+        Self {
+            prices: prices.into_iter().map(|(k, v)| (k.to_string(), v)).collect(),
+        }
+    }
+}
+
+impl PriceTable {
+    /// Exact match first, then the longest matching prefix.
+    pub fn lookup(&self, model: &str) -> Option<&ModelPrice> {
+        self.prices.get(model).or_else(|| {
+            self.prices
+                .iter()
+                .filter(|(key, _)| model.starts_with(key.as_str()))
+                .max_by_key(|(key, _)| key.len())
+                .map(|(_, price)| price)
+        })
+    }
+
+    /// Cost in USD, or `None` if the model has no known price.
+    pub fn cost(&self, model: &str, usage: &Usage) -> Option<f64> {
+        self.lookup(model).map(|price| price.cost(usage))
+    }
+}
+
+/// Hard spending limits. Any limit left unset is not enforced. Read from
+/// the `[budget]` config table.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Budget {
+    pub session_max_tokens: Option<u64>,
+    pub session_max_usd: Option<f64>,
+    pub request_max_tokens: Option<u64>,
+    pub request_max_usd: Option<f64>,
+    /// Fraction of any limit at which to warn, e.g. 0.8.
+    pub warn_at: f64,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Self {
+            session_max_tokens: None,
+            session_max_usd: None,
+            request_max_tokens: None,
+            request_max_usd: None,
+            warn_at: 0.8,
+        }
+    }
+}
+
+pub enum BudgetStatus {
+    Ok,
+    /// Past `warn_at` on the described limit.
+    Warning(String),
+    /// At or past the described limit.
+    Exceeded(String),
+}
+
+impl Budget {
+    /// Checks usage against every configured limit and reports the one
+    /// closest to (or furthest past) its cap. Costs are `None` when the
+    /// model has no known price, in which case dollar limits are skipped.
+    pub fn check(
+        &self,
+        request: &Usage,
+        request_cost: Option<f64>,
+        session: &Usage,
+        session_cost: Option<f64>,
+    ) -> BudgetStatus {
+        let tokens = |scope: &str, used: u64, max: Option<u64>| {
+            max.map(|max| (used as f64 / max.max(1) as f64, format!("{} tokens: {} of {}", scope, used, max)))
+        };
+        let dollars = |scope: &str, used: Option<f64>, max: Option<f64>| {
+            used.zip(max).map(|(used, max)| {
+                (used / max.max(f64::EPSILON), format!("{} cost: ${:.4} of ${:.2}", scope, used, max))
+            })
+        };
+
+        let worst = [
+            tokens("Session", session.total_tokens(), self.session_max_tokens),
+            dollars("Session", session_cost, self.session_max_usd),
+            tokens("Request", request.total_tokens(), self.request_max_tokens),
+            dollars("Request", request_cost, self.request_max_usd),
+        ]
+        .into_iter()
+        .flatten()
+        .max_by(|a, b| a.0.total_cmp(&b.0));
+
+        match worst {
+            Some((fraction, detail)) if fraction >= 1.0 => BudgetStatus::Exceeded(detail),
+            Some((fraction, detail)) if fraction >= self.warn_at => BudgetStatus::Warning(detail),
+            _ => BudgetStatus::Ok,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input_price(model: &str) -> Option<f64> {
+        PriceTable::default().lookup(model).map(|p| p.input)
+    }
+
+    #[test]
+    fn prefers_the_longest_prefix() {
+        assert_eq!(input_price("claude-opus-4-5"), Some(5.0));
+        assert_eq!(input_price("claude-opus-4-5-20251101"), Some(5.0));
+        assert_eq!(input_price("claude-opus-4-6"), Some(5.0));
+        assert_eq!(input_price("claude-opus-4-1-20250805"), Some(15.0));
+        assert_eq!(input_price("claude-opus-4-20250514"), Some(15.0));
+        assert_eq!(input_price("claude-sonnet-4-5-20250929"), Some(3.0));
+        assert_eq!(input_price("claude-haiku-4-5"), Some(1.0));
+        assert_eq!(input_price("gpt-4o"), None);
+    }
+
+    #[test]
+    fn prices_opus_4_5_usage() {
+        let usage = Usage {
+            input_tokens: 1_000_000,
+            output_tokens: 1_000_000,
+            ..Usage::default()
+        };
+        assert_eq!(PriceTable::default().cost("claude-opus-4-5-20251101", &usage), Some(30.0));
+    }
+}
@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::types::ToolError;
+
+/// What happens when a file tool reaches outside the workspace.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutsideAccess {
+    /// Refuse the call.
+    Deny,
+    /// Ask for approval, like a call the permission mode doesn't allow.
+    #[default]
+    Ask,
+}
+
+/// The directories the file tools may touch: the root plus any extra
+/// directories. Relative paths resolve against the root, `~` against the
+/// home directory, and every path is checked after symlinks are resolved,
+/// so neither `../..` nor a link out of the tree gets past it.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    root: PathBuf,
+    extra_dirs: Vec<PathBuf>,
+}
+
+impl Workspace {
+    /// Fails if `root` doesn't exist.
+    pub fn new(root: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            root: root.as_ref().canonicalize()?,
+            extra_dirs: Vec::new(),
+        })
+    }
+
+    /// The process's working directory. Fails if it can't be resolved, for
+    /// example because it was deleted.
+    pub fn current() -> io::Result<Self> {
+        std::env::current_dir().and_then(Self::new)
+    }
+
+    /// A workspace with no directories at all, for when there is no working
+    /// directory to root one at: every path is outside it.
+    pub(crate) fn detached() -> Self {
+        Self {
+            root: PathBuf::new(),
+            extra_dirs: Vec::new(),
+        }
+    }
+
+    /// Also allows `dir` and everything under it. Relative paths are taken
+    /// from the root.
+    pub fn with_dir(mut self, dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = self.root.join(expand_home(dir.as_ref())).canonicalize()?;
+        self.extra_dirs.push(dir);
+        Ok(self)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// `path` joined onto the root, without any checks.
+    pub fn resolve(&self, path: &str) -> PathBuf {
+        self.root.join(expand_home(Path::new(path)))
+    }
+
+    /// Resolves `path` and follows every symlink in it. Paths that don't
+    /// exist yet (a file about to be written) are resolved through their
+    /// nearest existing ancestor. Returns the real path, or an
+    /// `OutsideWorkspace` error carrying it.
+    pub fn check(&self, path: &str) -> Result<PathBuf, ToolError> {
+        let real = canonicalize_lenient(&self.resolve(path))?;
+        if self.contains(&real) {
+            Ok(real)
+        } else {
+            Err(ToolError::OutsideWorkspace(real.display().to_string()))
+        }
+    }
+
+    /// The root followed by the extra directories, all canonical.
+    pub fn dirs(&self) -> impl Iterator<Item = &Path> {
+        std::iter::once(self.root.as_path())
+            .filter(|root| !root.as_os_str().is_empty())
+            .chain(self.extra_dirs.iter().map(PathBuf::as_path))
+    }
+
+    pub fn contains(&self, real_path: &Path) -> bool {
+        self.dirs().any(|dir| real_path.starts_with(dir))
+    }
+
+    /// `path` relative to the root when it is inside it, for shorter output.
+    pub fn display(&self, path: &str) -> String {
+        Path::new(path)
+            .strip_prefix(&self.root)
+            .map(|relative| relative.display().to_string())
+            .unwrap_or_else(|_| path.to_string())
+    }
+}
+
+pub(crate) fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Like `canonicalize`, but the trailing components may be missing. Those
+/// can't be symlinks, so they are applied lexically to the real ancestor.
+fn canonicalize_lenient(path: &Path) -> io::Result<PathBuf> {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    let mut real = loop {
+        match existing.canonicalize() {
+            Ok(real) => break real,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let (Some(parent), Some(name)) = (existing.parent(), existing.components().next_back())
+                else {
+                    return Err(e);
+                };
+                missing.push(name);
+                existing = parent;
+            }
+            Err(e) => return Err(e),
+        }
+    };
+    for component in missing.into_iter().rev() {
+        match component {
+            Component::ParentDir => {
+                real.pop();
+            }
+            Component::Normal(name) => real.push(name),
+            _ => {}
+        }
+    }
+    Ok(real)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    /// A fresh directory holding `ws/` (the workspace) and `outside/`.
+    fn scratch() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "forge-workspace-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(dir.join("ws/src")).unwrap();
+        std::fs::create_dir_all(dir.join("outside")).unwrap();
+        std::fs::write(dir.join("outside/secret"), "x").unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    fn is_outside(result: Result<PathBuf, ToolError>) -> bool {
+        matches!(result, Err(ToolError::OutsideWorkspace(_)))
+    }
+
+    #[test]
+    fn paths_inside_the_root_pass() {
+        let dir = scratch();
+        let ws = Workspace::new(dir.join("ws")).unwrap();
+        assert_eq!(ws.check("src").unwrap(), dir.join("ws/src"));
+        assert_eq!(ws.check("src/../src/new.rs").unwrap(), dir.join("ws/src/new.rs"));
+        assert_eq!(ws.check(dir.join("ws").to_str().unwrap()).unwrap(), dir.join("ws"));
+    }
+
+    #[test]
+    fn dot_dot_cannot_leave_the_root() {
+        let dir = scratch();
+        let ws = Workspace::new(dir.join("ws")).unwrap();
+        assert!(is_outside(ws.check("../outside/secret")));
+        assert!(is_outside(ws.check("src/../../outside")));
+        assert!(is_outside(ws.check(dir.join("outside/secret").to_str().unwrap())));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_symlink_out_of_the_root_is_followed() {
+        let dir = scratch();
+        std::os::unix::fs::symlink(dir.join("outside"), dir.join("ws/link")).unwrap();
+        let ws = Workspace::new(dir.join("ws")).unwrap();
+        match ws.check("link/secret") {
+            Err(ToolError::OutsideWorkspace(real)) => {
+                assert_eq!(real, dir.join("outside/secret").display().to_string())
+            }
+            other => panic!("expected OutsideWorkspace, got {:?}", other),
+        }
+        // A file about to be created behind the link is outside too.
+        assert!(is_outside(ws.check("link/new.txt")));
+    }
+
+    #[test]
+    fn missing_paths_resolve_through_their_real_ancestor() {
+        let dir = scratch();
+        let ws = Workspace::new(dir.join("ws")).unwrap();
+        assert_eq!(ws.check("src/a/b/c.rs").unwrap(), dir.join("ws/src/a/b/c.rs"));
+        assert!(is_outside(ws.check("../outside/missing/file")));
+        assert!(is_outside(ws.check("src/missing/../../../outside/file")));
+        assert_eq!(
+            canonicalize_lenient(&dir.join("ws/missing/../src")).unwrap(),
+            dir.join("ws/src")
+        );
+    }
+
+    #[test]
+    fn additional_dirs_are_inside() {
+        let dir = scratch();
+        let ws = Workspace::new(dir.join("ws")).unwrap().with_dir("../outside").unwrap();
+        assert_eq!(ws.check("../outside/secret").unwrap(), dir.join("outside/secret"));
+        assert!(ws.contains(&dir.join("outside/new")));
+        assert!(ws.contains(&dir.join("ws/src")));
+        assert!(!ws.contains(&dir));
+        assert!(!ws.contains(&dir.join("outsider")));
+        assert_eq!(ws.dirs().count(), 2);
+        assert!(Workspace::new(dir.join("ws")).unwrap().with_dir("missing").is_err());
+    }
+
+    #[test]
+    fn a_detached_workspace_contains_nothing() {
+        let ws = Workspace::detached();
+        assert_eq!(ws.dirs().count(), 0);
+        assert!(!ws.contains(Path::new("/")));
+        assert!(!ws.contains(Path::new("relative")));
+    }
+}